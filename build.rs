@@ -14,10 +14,10 @@ fn main() {
         .file_descriptor_set_path(descriptor_path)
         .compile(
             &[
-                "proto-definitions/parquetb.proto",  // Path to your parquetb.proto
+                "proto/parquetb.proto",  // Path to your parquetb.proto
                 "proto-definitions/minioc.proto" // Path to your minioc.proto
             ], 
-            &["proto"]
+            &["proto", "proto-definitions"]
         )
         .unwrap_or_else(|e| panic!("Failed to compile proto files: {}", e));
 }
@@ -0,0 +1,50 @@
+
+use std::sync::Arc;
+
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::auth::token_store::{AdminCaller, AuthorizedTenant, TokenStore};
+
+// Checks the bearer token of every call and binds the call to the token's tenant, or marks it
+// as an admin's. Without a tenant token store, other calls are let through unauthenticated.
+#[derive(Debug, Clone)]
+pub struct AuthInterceptor {
+    tokens: Option<Arc<TokenStore>>,
+    admin_tokens: Option<Arc<TokenStore>>,
+}
+
+impl AuthInterceptor {
+    pub fn new(tokens: Option<Arc<TokenStore>>, admin_tokens: Option<Arc<TokenStore>>) -> Self {
+        AuthInterceptor { tokens, admin_tokens }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+
+        if let (Some(admin_tokens), Some(token)) = (&self.admin_tokens, &token) {
+            if let Some(admin) = admin_tokens.authenticate(token) {
+                request.extensions_mut().insert(AdminCaller(admin));
+                return Ok(request);
+            }
+        }
+
+        let Some(tokens) = &self.tokens else {
+            return Ok(request);
+        };
+        let token = token.ok_or_else(|| Status::unauthenticated("Missing bearer token"))?;
+        let tenant = tokens
+            .authenticate(&token)
+            .ok_or_else(|| Status::unauthenticated("Invalid bearer token"))?;
+
+        request.extensions_mut().insert(AuthorizedTenant(tenant));
+        Ok(request)
+    }
+}
@@ -0,0 +1,2 @@
+pub mod token_store;
+pub mod auth_interceptor;
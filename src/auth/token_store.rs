@@ -0,0 +1,105 @@
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+// Tenant a request was authenticated as, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct AuthorizedTenant(pub String);
+
+// Name of the admin a request was authenticated as, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct AdminCaller(pub String);
+
+// API tokens of each tenant, kept as SHA-256 hashes in a JSON file shaped as {"tenant": ["<hex hash>", ...]}
+#[derive(Debug)]
+pub struct TokenStore {
+    path: PathBuf,
+    tokens: RwLock<HashMap<String, String>>,
+    modified: RwLock<Option<SystemTime>>,
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn read_tokens(path: &Path) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let value: Value = serde_json::from_str(&content)?;
+
+    let tenants = value.as_object().ok_or("Token file must contain a JSON object keyed by tenant")?;
+
+    let mut tokens = HashMap::new();
+    for (tenant, hashes) in tenants {
+        let hashes = hashes
+            .as_array()
+            .ok_or_else(|| format!("Tokens of tenant {} must be a list", tenant))?;
+        for hash in hashes {
+            let hash = hash
+                .as_str()
+                .ok_or_else(|| format!("Tokens of tenant {} must be strings", tenant))?;
+            tokens.insert(hash.to_lowercase(), tenant.clone());
+        }
+    }
+
+    Ok(tokens)
+}
+
+impl TokenStore {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let tokens = read_tokens(path)?;
+        info!("Loaded {} API tokens from {:?}", tokens.len(), path);
+
+        Ok(TokenStore {
+            path: path.to_path_buf(),
+            tokens: RwLock::new(tokens),
+            modified: RwLock::new(std::fs::metadata(path)?.modified().ok()),
+        })
+    }
+
+    // Tenant the token belongs to, if any
+    pub fn authenticate(&self, token: &str) -> Option<String> {
+        self.tokens.read().unwrap().get(&hash_token(token)).cloned()
+    }
+
+    pub fn tenants(&self) -> Vec<String> {
+        let mut tenants: Vec<_> = self.tokens.read().unwrap().values().cloned().collect();
+        tenants.sort();
+        tenants.dedup();
+        tenants
+    }
+
+    // Reload the token file whenever it changes, so tokens can be added or revoked without a restart
+    pub fn spawn_reload(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+
+                let modified = std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok();
+                if modified == *self.modified.read().unwrap() {
+                    continue;
+                }
+
+                match read_tokens(&self.path) {
+                    Ok(tokens) => {
+                        info!("Reloaded {} API tokens from {:?}", tokens.len(), self.path);
+                        *self.tokens.write().unwrap() = tokens;
+                        *self.modified.write().unwrap() = modified;
+                    }
+                    // Keep serving with the previous tokens until the file is fixed
+                    Err(e) => error!("Failed to reload API tokens from {:?}: {}", self.path, e),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,286 @@
+pub mod minioc {
+    tonic::include_proto!("minioc");
+}
+
+use std::env;
+use std::error::Error;
+use std::num::NonZeroU64;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::Stream;
+use http::Uri;
+use sha2::{Digest, Sha256};
+use minioc::minioc_service_client::MiniocServiceClient;
+use minioc::FileChunk;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::transport::{Channel, Endpoint};
+use tonic::Request;
+use tracing::{error, info, warn};
+
+use crate::client::circuit_breaker::CircuitBreaker;
+use crate::routing::object_location::ObjectLocation;
+use crate::sinks::{bandwidth_limiter::BandwidthLimiter, storage_sink::{Delivery, SinkError}};
+use crate::tls::minioc_tls_config::minioc_tls_config;
+use crate::utils::file_digest::{file_digest, hex};
+
+// Request and response metadata carrying the SHA-256 and size of an uploaded file
+const CHECKSUM_HEADER: &str = "checksum-sha256";
+const SIZE_HEADER: &str = "content-size";
+// Request metadata carrying the location resolved by the routing table
+const OBJECT_KEY_HEADER: &str = "object-key";
+const BUCKET_HEADER: &str = "bucket";
+
+// Stream of file chunks sent to minioc
+struct FileChunkStream {
+    receiver: mpsc::Receiver<FileChunk>,
+}
+
+impl Stream for FileChunkStream {
+    type Item = FileChunk;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+// Room left in each message for the FileChunk fields other than the data, and the gRPC framing
+pub const CHUNK_OVERHEAD: usize = 64 * 1024;
+
+// How files are streamed to minioc, how the channel is kept alive and when it stops trying
+#[derive(Debug, Clone, Copy)]
+pub struct MiniocSettings {
+    // Bytes per FileChunk message
+    pub chunk_size: usize,
+    // Largest message minioc accepts, which a chunk and CHUNK_OVERHEAD must fit in
+    pub max_message_size: usize,
+    // Chunks read ahead of the upload
+    pub channel_depth: usize,
+    // An upload must finish within this time plus its size at `min_upload_bytes_per_sec`
+    pub upload_timeout: Duration,
+    pub min_upload_bytes_per_sec: NonZeroU64,
+    pub keepalive_interval: Duration,
+    pub keepalive_timeout: Duration,
+    // Consecutive failed uploads after which uploads fail fast
+    pub breaker_threshold: u32,
+    pub breaker_cooldown: Duration,
+    // Fail uploads minioc does not report a checksum and size for
+    pub require_verification: bool,
+}
+
+impl Default for MiniocSettings {
+    fn default() -> Self {
+        MiniocSettings {
+            chunk_size: 1024 * 1024,
+            max_message_size: 4 * 1024 * 1024,
+            channel_depth: 4,
+            upload_timeout: Duration::from_secs(30),
+            min_upload_bytes_per_sec: NonZeroU64::new(1024 * 1024).unwrap(),
+            keepalive_interval: Duration::from_secs(30),
+            keepalive_timeout: Duration::from_secs(10),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(30),
+            require_verification: false,
+        }
+    }
+}
+
+// Shared connection to minioc. The channel connects on first use, multiplexes concurrent uploads
+// over HTTP/2 and reconnects by itself; the breaker fails uploads fast while minioc is down.
+#[derive(Debug)]
+pub struct MiniocClient {
+    channel: Channel,
+    chunk_size: usize,
+    channel_depth: usize,
+    upload_timeout: Duration,
+    // Slowest throughput an upload may run at, unless the bandwidth limit is lower
+    min_upload_bytes_per_sec: NonZeroU64,
+    breaker: CircuitBreaker,
+    require_verification: bool,
+}
+
+impl MiniocClient {
+    // Client of the minioc service at MINIOC_DOMAIN:MINIOC_PORT, or None when it is not configured
+    pub fn from_env(settings: MiniocSettings) -> Result<Option<Self>, Box<dyn Error>> {
+        let (Ok(ip), Ok(port)) = (env::var("MINIOC_DOMAIN"), env::var("MINIOC_PORT")) else {
+            return Ok(None);
+        };
+        let tls = minioc_tls_config()?;
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        let addr: Uri = format!("{}://{}:{}", scheme, ip, port).parse()?;
+
+        let mut endpoint = Endpoint::from_shared(addr.to_string())?
+            .connect_timeout(Duration::from_secs(5))
+            .tcp_nodelay(true)
+            .tcp_keepalive(Some(Duration::from_secs(30)))
+            .http2_keep_alive_interval(settings.keepalive_interval)
+            .keep_alive_timeout(settings.keepalive_timeout)
+            .keep_alive_while_idle(true);
+        if let Some(tls) = tls {
+            endpoint = endpoint.tls_config(tls)?;
+        }
+        info!("minioc client configured for {}", addr);
+
+        Ok(Some(MiniocClient {
+            channel: endpoint.connect_lazy(),
+            chunk_size: settings.chunk_size.max(1),
+            channel_depth: settings.channel_depth.max(1),
+            upload_timeout: settings.upload_timeout,
+            min_upload_bytes_per_sec: settings.min_upload_bytes_per_sec,
+            breaker: CircuitBreaker::new(settings.breaker_threshold, settings.breaker_cooldown),
+            require_verification: settings.require_verification,
+        }))
+    }
+
+    pub async fn send_log(
+        &self,
+        file_path: &str,
+        tenant: &str,
+        location: &ObjectLocation,
+        bandwidth: Option<Arc<BandwidthLimiter>>,
+    ) -> Result<Delivery, SinkError> {
+        if let Err(retry_in) = self.breaker.allow() {
+            warn!("minioc circuit is open, not uploading {} for another {:?}", file_path, retry_in);
+            return Err(format!("minioc is unavailable, retry in {:?}", retry_in).into());
+        }
+
+        match self.upload(file_path, tenant, location, bandwidth).await {
+            Ok(delivery) => {
+                self.breaker.record_success();
+                Ok(delivery)
+            }
+            Err(e) => {
+                self.breaker.record_failure();
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload(
+        &self,
+        file_path: &str,
+        tenant: &str,
+        location: &ObjectLocation,
+        bandwidth: Option<Arc<BandwidthLimiter>>,
+    ) -> Result<Delivery, SinkError> {
+        info!("Starting send_log with file_path: {}, tenant: {}, object key: {}", file_path, tenant, location.key);
+
+        // Checksum and size announced to minioc before the content
+        let (checksum, size) = file_digest(file_path).await?;
+
+        // Open the file
+        let mut file = File::open(file_path).await.map_err(|e| {
+            error!("Failed to open file {}: {}", file_path, e);
+            e
+        })?;
+
+        // Slowest rate the upload may run at, never above the bandwidth limit
+        let min_rate = self.min_upload_bytes_per_sec.get() as f64;
+        let min_rate = bandwidth.as_ref().map_or(min_rate, |bandwidth| bandwidth.bytes_per_sec().min(min_rate));
+
+        // Create a channel to stream the file chunks
+        let (tx, rx) = mpsc::channel(self.channel_depth);
+
+        // Spawn a task to read the file in chunks and send to the channel, hashing what is sent
+        let chunk_size = self.chunk_size;
+        let reader = tokio::spawn(async move {
+            let mut hasher = Sha256::new();
+            let mut sent = 0u64;
+            loop {
+                let mut data = Vec::with_capacity(chunk_size);
+                match (&mut file).take(chunk_size as u64).read_to_end(&mut data).await {
+                    Ok(0) => {
+                        info!("Reached end of file");
+                        break;
+                    }
+                    Ok(n) => {
+                        if let Some(bandwidth) = &bandwidth {
+                            bandwidth.acquire(n).await;
+                        }
+                        hasher.update(&data);
+                        sent += n as u64;
+                        let chunk = FileChunk { data };
+                        if tx.send(chunk).await.is_err() {
+                            error!("Receiver dropped, stopping file read");
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error reading file: {}", e);
+                        break;
+                    }
+                }
+            }
+            (hex(&hasher.finalize()), sent)
+        });
+
+        // Create the request with the metadata headers
+        let mut request = Request::new(FileChunkStream { receiver: rx });
+        request.metadata_mut().insert("tenant", MetadataValue::from_str(tenant)?);
+        request.metadata_mut().insert("filename", MetadataValue::from_str(&location.object_name)?);
+        request.metadata_mut().insert(OBJECT_KEY_HEADER, MetadataValue::from_str(&location.key)?);
+        if let Some(bucket) = &location.bucket {
+            request.metadata_mut().insert(BUCKET_HEADER, MetadataValue::from_str(bucket)?);
+        }
+        request.metadata_mut().insert(CHECKSUM_HEADER, MetadataValue::from_str(&checksum)?);
+        request.metadata_mut().insert(SIZE_HEADER, MetadataValue::from(size));
+
+        // Uploads share the channel, each one on its own HTTP/2 stream. Big files get longer to stream.
+        let timeout = self.upload_timeout + Duration::from_secs_f64(size as f64 / min_rate);
+        let mut client = MiniocServiceClient::new(self.channel.clone());
+        let response = match tokio::time::timeout(timeout, client.stream_upload(request)).await {
+            Ok(response) => response.map_err(|e| {
+                error!("Failed to send stream_upload request: {}", e);
+                e
+            })?,
+            Err(_) => {
+                error!("Upload of {} did not finish within {:?}", file_path, timeout);
+                return Err(format!("Upload of {} timed out after {:?}", file_path, timeout).into());
+            }
+        };
+
+        // The bytes sent must be the ones announced, and the ones minioc received
+        let (sent_checksum, sent_size) = reader.await?;
+        if sent_checksum != checksum || sent_size != size {
+            return Err(format!(
+                "{} changed while uploading: announced {} bytes with SHA-256 {}, sent {} bytes with SHA-256 {}",
+                file_path, size, checksum, sent_size, sent_checksum
+            ).into());
+        }
+        let delivery = self.verify(response.metadata(), &checksum, size)?;
+
+        info!("Upload response: {}", response.into_inner().message);
+        Ok(delivery)
+    }
+
+    // Compare the checksum and size minioc reports with the ones sent
+    fn verify(&self, metadata: &MetadataMap, checksum: &str, size: u64) -> Result<Delivery, SinkError> {
+        let reported_checksum = metadata.get(CHECKSUM_HEADER).and_then(|value| value.to_str().ok());
+        let reported_size = metadata.get(SIZE_HEADER).and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+
+        match (reported_checksum, reported_size) {
+            (Some(reported_checksum), Some(reported_size)) => {
+                if !reported_checksum.eq_ignore_ascii_case(checksum) || reported_size != size {
+                    return Err(format!(
+                        "minioc stored {} bytes with SHA-256 {}, expected {} bytes with SHA-256 {}",
+                        reported_size, reported_checksum, size, checksum
+                    ).into());
+                }
+                info!("minioc verified {} bytes with SHA-256 {}", size, checksum);
+                Ok(Delivery::Verified)
+            }
+            _ if self.require_verification => Err("minioc did not report the checksum and size it stored".into()),
+            _ => {
+                warn!("minioc did not report the checksum and size it stored, the upload is not verified");
+                Ok(Delivery::Unverified)
+            }
+        }
+    }
+}
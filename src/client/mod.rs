@@ -1 +1,2 @@
-pub mod send_log;
+pub mod minioc_client;
+pub mod circuit_breaker;
@@ -0,0 +1,298 @@
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tracing::{info, warn};
+
+const DEFAULT_MAX_KEYS: usize = 1_000_000;
+
+// Natural key of a tenant's rows, how long a key is remembered and how many keys at most
+#[derive(Debug, Clone)]
+pub struct DedupSettings {
+    // Entry fields making up the key, "metadata.<key>" for metadata values
+    pub key_fields: Vec<String>,
+    pub window: Duration,
+    // The oldest keys are forgotten early once the window holds this many
+    pub max_keys: usize,
+}
+
+impl DedupSettings {
+    pub fn from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let key_fields = value["key"]
+            .as_array()
+            .ok_or("'dedup' is missing its 'key' field list")?
+            .iter()
+            .map(|field| field.as_str().map(str::to_string).ok_or("'dedup.key' must be a list of strings"))
+            .collect::<Result<Vec<_>, _>>()?;
+        if key_fields.is_empty() {
+            return Err("'dedup.key' must not be empty".into());
+        }
+
+        let window = Duration::from_secs(value["window_secs"].as_u64().unwrap_or(0));
+
+        let max_keys = match value.get("max_keys") {
+            Some(max_keys) => match max_keys.as_u64() {
+                Some(max_keys) if max_keys > 0 => max_keys as usize,
+                _ => return Err("'dedup.max_keys' must be a positive integer".into()),
+            },
+            None => DEFAULT_MAX_KEYS,
+        };
+
+        Ok(DedupSettings { key_fields, window, max_keys })
+    }
+
+    pub fn natural_key(&self, log_entry: &Value) -> String {
+        self.key_fields
+            .iter()
+            .map(|field| {
+                let value = match field.strip_prefix("metadata.") {
+                    Some(metadata_key) => &log_entry["metadata"][metadata_key],
+                    None => &log_entry[field.as_str()],
+                };
+                match value {
+                    Value::String(value) => value.clone(),
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\u{1f}")
+    }
+}
+
+#[derive(Default)]
+struct DedupWindow {
+    // Each key with the time it was recorded
+    seen: HashMap<String, Instant>,
+    // Keys in recording order. Entries whose key was released or recorded again are stale.
+    expiry: VecDeque<(Instant, String)>,
+}
+
+impl DedupWindow {
+    fn evict_expired(&mut self, window: Duration) {
+        while self.expiry.front().is_some_and(|(recorded_at, _)| recorded_at.elapsed() >= window) {
+            self.evict_oldest();
+        }
+    }
+
+    // Forget the oldest keys while the window holds more than `max_keys`
+    fn evict_over(&mut self, max_keys: usize) -> usize {
+        let mut evicted = 0;
+        while self.expiry.len() > max_keys {
+            evicted += self.evict_oldest() as usize;
+        }
+        evicted
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        let Some((recorded_at, key)) = self.expiry.pop_front() else {
+            return false;
+        };
+        self.forget(&key, recorded_at)
+    }
+
+    // Remove a key, unless it was recorded again since
+    fn forget(&mut self, key: &str, recorded_at: Instant) -> bool {
+        if self.seen.get(key) == Some(&recorded_at) {
+            self.seen.remove(key);
+            return true;
+        }
+        false
+    }
+}
+
+// Entries left after removing duplicates
+pub struct UniqueEntries<'a> {
+    pub log_entries: Vec<Value>,
+    pub dropped: usize,
+    pub reservation: DedupReservation<'a>,
+}
+
+// Keys of a batch being written, already in the window so concurrent batches see them.
+// Dropped without `keep`, for instance when the write fails, it forgets them again.
+pub struct DedupReservation<'a> {
+    deduplicator: &'a Deduplicator,
+    table: TableKey,
+    keys: Vec<String>,
+    recorded_at: Instant,
+}
+
+impl DedupReservation<'_> {
+    // The entries are committed, remember their keys for the rest of the window
+    pub fn keep(mut self) {
+        self.keys.clear();
+    }
+}
+
+impl Drop for DedupReservation<'_> {
+    fn drop(&mut self) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let mut windows = self.deduplicator.windows.lock().unwrap();
+        if let Some(window) = windows.get_mut(&self.table) {
+            for key in &self.keys {
+                window.forget(key, self.recorded_at);
+            }
+        }
+    }
+}
+
+// Tenant and table name, the default table being ""
+type TableKey = (String, String);
+
+// Natural keys committed to each table within its tenant's sliding window
+#[derive(Default)]
+pub struct Deduplicator {
+    windows: Mutex<HashMap<TableKey, DedupWindow>>,
+}
+
+impl std::fmt::Debug for Deduplicator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Deduplicator").finish_non_exhaustive()
+    }
+}
+
+impl Deduplicator {
+    // Drop the entries whose natural key repeats within the batch or was recorded within the
+    // table's window, and record the keys of the others right away
+    pub fn filter(&self, tenant: &str, table: &str, settings: &DedupSettings, log_entries: Vec<Value>) -> UniqueEntries<'_> {
+        let table_key = (tenant.to_string(), table.to_string());
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(table_key.clone()).or_default();
+        window.evict_expired(settings.window);
+
+        let mut batch_keys = HashSet::new();
+        let mut unique = Vec::with_capacity(log_entries.len());
+        let mut keys = Vec::with_capacity(log_entries.len());
+        let mut dropped = 0;
+
+        for log_entry in log_entries {
+            let key = settings.natural_key(&log_entry);
+            if window.seen.contains_key(&key) || !batch_keys.insert(key.clone()) {
+                dropped += 1;
+                continue;
+            }
+            keys.push(key);
+            unique.push(log_entry);
+        }
+
+        if dropped > 0 {
+            info!("Dropped {} duplicate entries for tenant {}", dropped, tenant);
+        }
+
+        // Without a window, keys only matter within the batch
+        let recorded_at = Instant::now();
+        if settings.window.is_zero() {
+            keys.clear();
+        }
+        for key in &keys {
+            window.seen.insert(key.clone(), recorded_at);
+            window.expiry.push_back((recorded_at, key.clone()));
+        }
+        let evicted = window.evict_over(settings.max_keys);
+        if evicted > 0 {
+            warn!("Dedup window of tenant {} table {:?} is full, forgot its {} oldest keys", tenant, table, evicted);
+        }
+
+        let reservation = DedupReservation { deduplicator: self, table: table_key, keys, recorded_at };
+        UniqueEntries { log_entries: unique, dropped, reservation }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn settings(window: Duration, max_keys: usize) -> DedupSettings {
+        DedupSettings { key_fields: vec!["id".to_string()], window, max_keys }
+    }
+
+    fn entries(ids: &[u64]) -> Vec<Value> {
+        ids.iter().map(|id| json!({ "id": id })).collect()
+    }
+
+    #[test]
+    fn drops_duplicates_within_batch_and_window() {
+        let deduplicator = Deduplicator::default();
+        let settings = settings(Duration::from_secs(60), 100);
+
+        let unique = deduplicator.filter("acme", "", &settings, entries(&[1, 2, 1]));
+        assert_eq!(unique.log_entries, entries(&[1, 2]));
+        assert_eq!(unique.dropped, 1);
+        unique.reservation.keep();
+
+        let unique = deduplicator.filter("acme", "", &settings, entries(&[2, 3]));
+        assert_eq!(unique.log_entries, entries(&[3]));
+        assert_eq!(unique.dropped, 1);
+    }
+
+    #[test]
+    fn windows_are_per_table() {
+        let deduplicator = Deduplicator::default();
+        let settings = settings(Duration::from_secs(60), 100);
+
+        deduplicator.filter("acme", "orders", &settings, entries(&[1])).reservation.keep();
+        assert_eq!(deduplicator.filter("acme", "events", &settings, entries(&[1])).dropped, 0);
+        assert_eq!(deduplicator.filter("other", "orders", &settings, entries(&[1])).dropped, 0);
+    }
+
+    #[test]
+    fn dropped_reservation_forgets_its_keys() {
+        let deduplicator = Deduplicator::default();
+        let settings = settings(Duration::from_secs(60), 100);
+
+        let unique = deduplicator.filter("acme", "", &settings, entries(&[1]));
+        // A concurrent batch already sees the reserved key
+        assert_eq!(deduplicator.filter("acme", "", &settings, entries(&[1])).dropped, 1);
+        drop(unique);
+
+        assert_eq!(deduplicator.filter("acme", "", &settings, entries(&[1])).dropped, 0);
+    }
+
+    #[test]
+    fn released_key_recorded_again_is_kept() {
+        let deduplicator = Deduplicator::default();
+        let settings = settings(Duration::from_secs(60), 100);
+
+        let first = deduplicator.filter("acme", "", &settings, entries(&[1]));
+        let mut windows = deduplicator.windows.lock().unwrap();
+        let window = windows.get_mut(&("acme".to_string(), String::new())).unwrap();
+        // The key was recorded again by a later batch, releasing the first must not forget it
+        window.seen.insert("1".to_string(), first.reservation.recorded_at + Duration::from_millis(1));
+        drop(windows);
+        drop(first);
+
+        assert_eq!(deduplicator.filter("acme", "", &settings, entries(&[1])).dropped, 1);
+    }
+
+    #[test]
+    fn evicts_expired_and_oldest_keys() {
+        let deduplicator = Deduplicator::default();
+
+        let expiring = settings(Duration::from_millis(1), 100);
+        deduplicator.filter("acme", "", &expiring, entries(&[1])).reservation.keep();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(deduplicator.filter("acme", "", &expiring, entries(&[1])).dropped, 0);
+
+        let bounded = settings(Duration::from_secs(60), 2);
+        deduplicator.filter("other", "", &bounded, entries(&[1, 2, 3])).reservation.keep();
+        let unique = deduplicator.filter("other", "", &bounded, entries(&[1, 2, 3]));
+        assert_eq!(unique.log_entries, entries(&[1]));
+    }
+
+    #[test]
+    fn zero_window_only_drops_within_batch() {
+        let deduplicator = Deduplicator::default();
+        let settings = settings(Duration::ZERO, 100);
+
+        let unique = deduplicator.filter("acme", "", &settings, entries(&[1, 1]));
+        assert_eq!(unique.dropped, 1);
+        unique.reservation.keep();
+        assert_eq!(deduplicator.filter("acme", "", &settings, entries(&[1])).dropped, 0);
+    }
+}
@@ -0,0 +1,121 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpStream;
+use tonic::server::NamedService;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use tracing::{info, warn};
+
+use crate::parquetb_service::parquetb::parquetb_service_server::ParquetbServiceServer;
+use crate::parquetb_service::MyParquetbService;
+use crate::shutdown::drain_signal::DrainSignal;
+
+// When the health service reports NOT_SERVING
+#[derive(Debug, Clone)]
+pub struct HealthSettings {
+    // How often minioc and the disk are checked
+    pub interval: Duration,
+    // Whether any destination is minioc; otherwise it is never probed
+    pub probe_minioc: bool,
+    // How long minioc may stay unreachable before the instance is unhealthy
+    pub minioc_grace: Duration,
+    // Free space of the disks holding these directories, in percent, below which the
+    // instance is unhealthy
+    pub disk_paths: Vec<PathBuf>,
+    pub min_free_percent: f64,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        HealthSettings {
+            interval: Duration::from_secs(5),
+            probe_minioc: true,
+            minioc_grace: Duration::from_secs(60),
+            disk_paths: vec![PathBuf::from(".")],
+            min_free_percent: 5.0,
+        }
+    }
+}
+
+// Periodically check minioc and the spool and output disks, and publish the result on grpc.health.v1.Health.
+// Once the server drains, it stays NOT_SERVING.
+pub fn spawn_health_monitor(settings: HealthSettings, mut reporter: HealthReporter, drain: DrainSignal) {
+    let minioc_addr = match (env::var("MINIOC_DOMAIN"), env::var("MINIOC_PORT")) {
+        (Ok(ip), Ok(port)) if settings.probe_minioc => Some(format!("{}:{}", ip, port)),
+        _ => None,
+    };
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(settings.interval);
+        let mut unreachable_since: Option<Instant> = None;
+        let mut reported = None;
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = drain.draining() => {
+                    info!("Health is now NOT_SERVING while the server drains");
+                    set_status(&mut reporter, ServingStatus::NotServing).await;
+                    return;
+                }
+            }
+
+            let minioc_reachable = match &minioc_addr {
+                Some(addr) => probe(addr, settings.interval).await,
+                None => true,
+            };
+            if minioc_reachable {
+                unreachable_since = None;
+            } else if unreachable_since.is_none() {
+                warn!("minioc at {:?} is unreachable", minioc_addr);
+                unreachable_since = Some(Instant::now());
+            }
+            let minioc_down = unreachable_since.is_some_and(|since| since.elapsed() >= settings.minioc_grace);
+
+            // The fullest disk decides
+            let free_percent = settings.disk_paths.iter()
+                .filter_map(|path| free_percent(path))
+                .min_by(f64::total_cmp);
+            let disk_full = free_percent.is_some_and(|free| free < settings.min_free_percent);
+
+            let status = if minioc_down || disk_full {
+                ServingStatus::NotServing
+            } else {
+                ServingStatus::Serving
+            };
+            if reported != Some(status) {
+                info!(
+                    "Health is now {:?} (minioc down: {}, free disk: {:?}%)",
+                    status, minioc_down, free_percent
+                );
+                set_status(&mut reporter, status).await;
+                reported = Some(status);
+            }
+        }
+    });
+}
+
+// Report the same status for the server as a whole and for ParquetbService
+async fn set_status(reporter: &mut HealthReporter, status: ServingStatus) {
+    reporter.set_service_status("", status).await;
+    reporter
+        .set_service_status(<ParquetbServiceServer<MyParquetbService> as NamedService>::NAME, status)
+        .await;
+}
+
+// Whether a TCP connection to the address opens in time
+async fn probe(addr: &str, timeout: Duration) -> bool {
+    matches!(tokio::time::timeout(timeout, TcpStream::connect(addr)).await, Ok(Ok(_)))
+}
+
+// Free space of the disk holding the path, in percent
+fn free_percent(path: &Path) -> Option<f64> {
+    let total = fs2::total_space(path).ok()?;
+    let available = fs2::available_space(path).ok()?;
+    if total == 0 {
+        return None;
+    }
+    Some(available as f64 * 100.0 / total as f64)
+}
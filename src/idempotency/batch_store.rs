@@ -0,0 +1,270 @@
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use tracing::{error, info};
+
+use crate::parquetb_service::parquetb::UploadResponse;
+
+// Request metadata header carrying the client-supplied batch ID
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+// Records appended to the log before it is rewritten with only the live batches
+const MIN_COMPACTION_RECORDS: usize = 1024;
+
+struct CompletedBatch {
+    response: UploadResponse,
+    expires_at: u64,
+}
+
+#[derive(Default)]
+struct BatchState {
+    completed: HashMap<String, CompletedBatch>,
+    in_flight: HashSet<String>,
+    // Open log file and the number of records in it, live or expired
+    log: Option<File>,
+    records: usize,
+}
+
+// Record of the batches completed in the last `ttl`, persisted as a log with one JSON record per
+// line. Each completion appends a record, and the log is compacted once it holds twice as many
+// records as there are live batches.
+pub struct BatchStore {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    state: Mutex<BatchState>,
+}
+
+pub enum BatchStatus<'a> {
+    // The batch was already written, here is the original response
+    Completed(UploadResponse),
+    // Another request with the same key is being processed
+    InFlight,
+    // The caller owns the key until the guard is completed or dropped
+    Started(InFlightBatch<'a>),
+}
+
+impl std::fmt::Debug for BatchStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchStore").field("path", &self.path).field("ttl", &self.ttl).finish()
+    }
+}
+
+impl Default for BatchStore {
+    fn default() -> Self {
+        BatchStore {
+            path: None,
+            ttl: Duration::from_secs(24 * 60 * 60),
+            state: Mutex::new(BatchState::default()),
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+impl BatchStore {
+    pub fn load(path: &Path, ttl: Duration) -> Result<Self, Box<dyn Error>> {
+        let mut completed = HashMap::new();
+
+        if path.exists() {
+            let now = now_secs();
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record: Value = serde_json::from_str(&line)?;
+                let key = record["key"].as_str().ok_or("Completed batch record must have a key")?;
+                let expires_at = record["expires_at"].as_u64().unwrap_or_default();
+                if expires_at <= now {
+                    continue;
+                }
+                let response = response_from_json(&record["response"]);
+                completed.insert(key.to_string(), CompletedBatch { response, expires_at });
+            }
+        }
+        info!("Loaded {} completed batches from {:?}", completed.len(), path);
+
+        // Start from a compacted log without the expired records
+        let log = compact(path, &completed)?;
+        let records = completed.len();
+
+        Ok(BatchStore {
+            path: Some(path.to_path_buf()),
+            ttl,
+            state: Mutex::new(BatchState { completed, in_flight: HashSet::new(), log: Some(log), records }),
+        })
+    }
+
+    pub fn begin(&self, key: &str) -> BatchStatus<'_> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = now_secs();
+        if let Some(batch) = state.completed.get(key) {
+            if batch.expires_at > now {
+                return BatchStatus::Completed(batch.response.clone());
+            }
+        }
+        if !state.in_flight.insert(key.to_string()) {
+            return BatchStatus::InFlight;
+        }
+
+        BatchStatus::Started(InFlightBatch { store: self, key: key.to_string() })
+    }
+
+    fn complete(&self, key: &str, response: &UploadResponse) {
+        let mut state = self.state.lock().unwrap();
+
+        let now = now_secs();
+        let expires_at = now + self.ttl.as_secs();
+        state.completed.retain(|_, batch| batch.expires_at > now);
+        state.completed.insert(key.to_string(), CompletedBatch { response: response.clone(), expires_at });
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        let record = json!({
+            "key": key,
+            "expires_at": expires_at,
+            "response": response_to_json(response),
+        });
+        if let Err(e) = append(&mut state, &record) {
+            error!("Failed to persist completed batch to {:?}: {}", path, e);
+        }
+
+        if state.records >= MIN_COMPACTION_RECORDS.max(2 * state.completed.len()) {
+            match compact(path, &state.completed) {
+                Ok(log) => {
+                    state.log = Some(log);
+                    state.records = state.completed.len();
+                }
+                Err(e) => error!("Failed to compact completed batches in {:?}: {}", path, e),
+            }
+        }
+    }
+
+    fn release(&self, key: &str) {
+        self.state.lock().unwrap().in_flight.remove(key);
+    }
+}
+
+// Ownership of an idempotency key while its batch is processed.
+// Dropping it without completing lets the client retry the key.
+pub struct InFlightBatch<'a> {
+    store: &'a BatchStore,
+    key: String,
+}
+
+impl InFlightBatch<'_> {
+    pub fn complete(self, response: &UploadResponse) {
+        self.store.complete(&self.key, response);
+    }
+}
+
+impl Drop for InFlightBatch<'_> {
+    fn drop(&mut self) {
+        self.store.release(&self.key);
+    }
+}
+
+fn append(state: &mut BatchState, record: &Value) -> Result<(), Box<dyn Error>> {
+    let log = state.log.as_mut().ok_or("Completed batches log is not open")?;
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    log.write_all(&line)?;
+    state.records += 1;
+    Ok(())
+}
+
+// Rewrite the log with only the given batches and reopen it for appending
+fn compact(path: &Path, completed: &HashMap<String, CompletedBatch>) -> Result<File, Box<dyn Error>> {
+    let mut content = Vec::new();
+    for (key, batch) in completed {
+        let record = json!({
+            "key": key,
+            "expires_at": batch.expires_at,
+            "response": response_to_json(&batch.response),
+        });
+        serde_json::to_writer(&mut content, &record)?;
+        content.push(b'\n');
+    }
+
+    // Write to a temporary file first so a crash never leaves a truncated file
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(OpenOptions::new().append(true).open(path)?)
+}
+
+fn response_to_json(response: &UploadResponse) -> Value {
+    json!({
+        "message": response.message,
+        "rows_committed": response.rows_committed,
+        "partial": response.partial,
+        "rows_skipped": response.rows_skipped,
+        "duplicates_dropped": response.duplicates_dropped,
+        "object_key": response.object_key,
+        "bucket": response.bucket,
+        "rows_received": response.rows_received,
+    })
+}
+
+fn response_from_json(value: &Value) -> UploadResponse {
+    UploadResponse {
+        message: value["message"].as_str().unwrap_or_default().to_string(),
+        rows_committed: value["rows_committed"].as_u64().unwrap_or_default(),
+        partial: value["partial"].as_bool().unwrap_or_default(),
+        rows_skipped: value["rows_skipped"].as_u64().unwrap_or_default(),
+        duplicates_dropped: value["duplicates_dropped"].as_u64().unwrap_or_default(),
+        object_key: value["object_key"].as_str().unwrap_or_default().to_string(),
+        bucket: value["bucket"].as_str().unwrap_or_default().to_string(),
+        rows_received: value["rows_received"].as_u64().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(rows: u64) -> UploadResponse {
+        UploadResponse { message: "ok".to_string(), rows_committed: rows, rows_received: rows, ..Default::default() }
+    }
+
+    #[test]
+    fn completed_batches_survive_a_reload() {
+        let path = std::env::temp_dir().join(format!("parquetb-batches-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = BatchStore::load(&path, Duration::from_secs(60)).unwrap();
+        for (key, rows) in [("a", 1), ("b", 2)] {
+            let BatchStatus::Started(batch) = store.begin(key) else {
+                panic!("key {} should be new", key);
+            };
+            batch.complete(&response(rows));
+        }
+        drop(store);
+
+        let store = BatchStore::load(&path, Duration::from_secs(60)).unwrap();
+        assert!(matches!(store.begin("a"), BatchStatus::Completed(r) if r == response(1)));
+        assert!(matches!(store.begin("b"), BatchStatus::Completed(r) if r == response(2)));
+        assert!(matches!(store.begin("c"), BatchStatus::Started(_)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn in_flight_key_is_released_on_drop() {
+        let store = BatchStore::default();
+        let started = store.begin("a");
+        assert!(matches!(store.begin("a"), BatchStatus::InFlight));
+        drop(started);
+        assert!(matches!(store.begin("a"), BatchStatus::Started(_)));
+    }
+}
@@ -0,0 +1,250 @@
+
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use parquet::file::metadata::KeyValue;
+use tonic::metadata::MetadataMap;
+use tonic::Request;
+
+use crate::auth::token_store::{AdminCaller, AuthorizedTenant};
+use crate::parquetb_error::ParquetbError;
+use crate::parquetb_service::parquetb::LogEntry;
+
+// Request metadata headers holding the defaults of a whole ingestion call
+pub const TENANT_HEADER: &str = "tenant";
+pub const TABLE_HEADER: &str = "table";
+// Former name of the table header, still accepted
+pub const SCHEMA_HEADER: &str = "schema";
+pub const COMPRESSION_HEADER: &str = "compression";
+pub const MAX_ROW_GROUP_SIZE_HEADER: &str = "max-row-group-size";
+
+// Tenant, table and write options shared by every entry of an ingestion call
+#[derive(Debug, Default, Clone)]
+pub struct IngestOptions {
+    pub tenant: Option<String>,
+    pub table: Option<String>,
+    pub compression: Option<Compression>,
+    pub max_row_group_size: Option<usize>,
+    // The tenant is bound to the caller's token and cannot be overridden
+    pub authorized: bool,
+}
+
+impl IngestOptions {
+    pub fn from_metadata(metadata: &MetadataMap) -> Result<Self, ParquetbError> {
+        let tenant = header(metadata, TENANT_HEADER)?;
+
+        let table = match (header(metadata, TABLE_HEADER)?, header(metadata, SCHEMA_HEADER)?) {
+            (Some(table), Some(schema)) if table != schema => {
+                return Err(ParquetbError::Validation(format!(
+                    "'{}' header {} does not match '{}' header {}",
+                    TABLE_HEADER, table, SCHEMA_HEADER, schema
+                )));
+            }
+            (table, schema) => table.or(schema),
+        };
+        if let Some(table) = &table {
+            validate_table(table)?;
+        }
+
+        let compression = header(metadata, COMPRESSION_HEADER)?.as_deref().map(parse_compression).transpose()?;
+        let max_row_group_size = match header(metadata, MAX_ROW_GROUP_SIZE_HEADER)? {
+            Some(size) => Some(size.parse::<usize>().ok().filter(|size| *size > 0).ok_or_else(|| {
+                ParquetbError::Validation(format!("Invalid max row group size: {}", size))
+            })?),
+            None => None,
+        };
+
+        Ok(IngestOptions { tenant, table, compression, max_row_group_size, authorized: false })
+    }
+
+    // Read the options of a call, binding it to the tenant its token was issued for
+    pub fn from_request<T>(request: &Request<T>) -> Result<Self, ParquetbError> {
+        if let Some(AdminCaller(admin)) = request.extensions().get::<AdminCaller>() {
+            return Err(ParquetbError::Unauthorized(format!(
+                "Admin token of {} only grants ListUndeliverableUploads",
+                admin
+            )));
+        }
+        let mut options = Self::from_metadata(request.metadata())?;
+
+        if let Some(AuthorizedTenant(tenant)) = request.extensions().get::<AuthorizedTenant>() {
+            if options.tenant.as_ref().is_some_and(|requested| requested != tenant) {
+                return Err(ParquetbError::Unauthorized(format!(
+                    "Token is not authorized for tenant {}",
+                    options.tenant.unwrap_or_default()
+                )));
+            }
+            options.tenant = Some(tenant.clone());
+            options.authorized = true;
+        }
+
+        Ok(options)
+    }
+
+    // Fill in the entry's tenant and table from the call defaults, or check they agree with them.
+    // The first entry naming a tenant or a table sets it for the rest of the call.
+    pub fn resolve_entry(&mut self, entry: &mut LogEntry) -> Result<(), ParquetbError> {
+        resolve(&mut self.tenant, &mut entry.tenant_name, "tenant").map_err(|e| match e {
+            ParquetbError::Validation(message) if self.authorized => ParquetbError::Unauthorized(message),
+            other => other,
+        })?;
+
+        let table_was_set = self.table.is_some();
+        resolve(&mut self.table, &mut entry.table, "table")?;
+        if !table_was_set {
+            if let Some(table) = &self.table {
+                validate_table(table)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Take the write options of a request body, which must agree with the headers. Empty and 0
+    // values are unset.
+    pub fn resolve_write_options(&mut self, compression: &str, max_row_group_size: u64) -> Result<(), ParquetbError> {
+        if !compression.is_empty() {
+            let compression = parse_compression(compression)?;
+            match self.compression {
+                Some(expected) if expected != compression => {
+                    return Err(ParquetbError::Validation(format!(
+                        "Request compression {} does not match '{}' header {}",
+                        compression, COMPRESSION_HEADER, expected
+                    )));
+                }
+                _ => self.compression = Some(compression),
+            }
+        }
+
+        if max_row_group_size > 0 {
+            let max_row_group_size = usize::try_from(max_row_group_size).map_err(|_| {
+                ParquetbError::Validation(format!("Invalid max row group size: {}", max_row_group_size))
+            })?;
+            match self.max_row_group_size {
+                Some(expected) if expected != max_row_group_size => {
+                    return Err(ParquetbError::Validation(format!(
+                        "Request max row group size {} does not match '{}' header {}",
+                        max_row_group_size, MAX_ROW_GROUP_SIZE_HEADER, expected
+                    )));
+                }
+                _ => self.max_row_group_size = Some(max_row_group_size),
+            }
+        }
+        Ok(())
+    }
+
+    // Writer settings of the request, with the given metadata stored in the file footer
+    pub fn writer_properties(&self, key_value_metadata: Vec<KeyValue>) -> WriterProperties {
+        let mut builder = WriterProperties::builder().set_key_value_metadata(Some(key_value_metadata));
+        if let Some(compression) = self.compression {
+            builder = builder.set_compression(compression);
+        }
+        if let Some(max_row_group_size) = self.max_row_group_size {
+            builder = builder.set_max_row_group_size(max_row_group_size);
+        }
+        builder.build()
+    }
+}
+
+fn resolve(expected: &mut Option<String>, value: &mut String, name: &str) -> Result<(), ParquetbError> {
+    match expected {
+        Some(expected) if value.is_empty() => *value = expected.clone(),
+        Some(expected) if value != expected => {
+            return Err(ParquetbError::Validation(format!(
+                "Entry {} {} does not match request {} {}",
+                name, value, name, expected
+            )));
+        }
+        Some(_) => {}
+        None if value.is_empty() => {}
+        None => *expected = Some(value.clone()),
+    }
+    Ok(())
+}
+
+fn parse_compression(compression: &str) -> Result<Compression, ParquetbError> {
+    compression
+        .parse::<Compression>()
+        .map_err(|e| ParquetbError::Validation(format!("Invalid compression {}: {}", compression, e)))
+}
+
+// Table names end up in file and object names
+fn validate_table(table: &str) -> Result<(), ParquetbError> {
+    if table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        Ok(())
+    } else {
+        Err(ParquetbError::Validation(format!("Invalid table name: {}", table)))
+    }
+}
+
+fn header(metadata: &MetadataMap, name: &str) -> Result<Option<String>, ParquetbError> {
+    match metadata.get(name) {
+        Some(value) => match value.to_str() {
+            Ok(value) if !value.is_empty() => Ok(Some(value.to_string())),
+            _ => Err(ParquetbError::Validation(format!("Invalid '{}' header", name))),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tenant_name: &str, table: &str) -> LogEntry {
+        LogEntry { tenant_name: tenant_name.to_string(), table: table.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn entries_inherit_the_call_defaults() {
+        let mut options = IngestOptions { tenant: Some("acme".to_string()), table: Some("orders".to_string()), ..Default::default() };
+        let mut log_entry = entry("", "");
+        options.resolve_entry(&mut log_entry).unwrap();
+        assert_eq!((log_entry.tenant_name.as_str(), log_entry.table.as_str()), ("acme", "orders"));
+    }
+
+    #[test]
+    fn first_entry_sets_the_defaults() {
+        let mut options = IngestOptions::default();
+        options.resolve_entry(&mut entry("acme", "orders")).unwrap();
+
+        let mut log_entry = entry("", "");
+        options.resolve_entry(&mut log_entry).unwrap();
+        assert_eq!((log_entry.tenant_name.as_str(), log_entry.table.as_str()), ("acme", "orders"));
+        assert!(matches!(options.resolve_entry(&mut entry("other", "")), Err(ParquetbError::Validation(_))));
+        assert!(matches!(options.resolve_entry(&mut entry("", "events")), Err(ParquetbError::Validation(_))));
+    }
+
+    #[test]
+    fn entry_without_table_keeps_the_default_table() {
+        let mut options = IngestOptions::default();
+        options.resolve_entry(&mut entry("acme", "")).unwrap();
+        assert_eq!(options.table, None);
+    }
+
+    #[test]
+    fn other_tenant_of_a_token_is_unauthorized() {
+        let mut options = IngestOptions { tenant: Some("acme".to_string()), authorized: true, ..Default::default() };
+        assert!(matches!(options.resolve_entry(&mut entry("other", "")), Err(ParquetbError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_table_names() {
+        let mut options = IngestOptions::default();
+        assert!(matches!(options.resolve_entry(&mut entry("acme", "../orders")), Err(ParquetbError::Validation(_))));
+    }
+
+    #[test]
+    fn write_options_fill_in_or_match_the_headers() {
+        let mut options = IngestOptions::default();
+        options.resolve_write_options("", 0).unwrap();
+        assert_eq!(options.compression, None);
+        assert_eq!(options.max_row_group_size, None);
+
+        options.resolve_write_options("SNAPPY", 1000).unwrap();
+        assert_eq!(options.compression, Some(Compression::SNAPPY));
+        assert_eq!(options.max_row_group_size, Some(1000));
+
+        assert!(matches!(options.resolve_write_options("UNCOMPRESSED", 0), Err(ParquetbError::Validation(_))));
+        assert!(matches!(options.resolve_write_options("", 10), Err(ParquetbError::Validation(_))));
+        assert!(matches!(options.resolve_write_options("fast", 0), Err(ParquetbError::Validation(_))));
+    }
+}
@@ -1,17 +1,54 @@
 
 mod parquetb_service;
+mod parquetb_error;
 mod utils;
 mod client;
+mod rules;
+mod offsets;
+mod idempotency;
+mod tenants;
+mod dedup;
+mod ingest;
+mod tables;
+mod auth;
+mod tls;
+mod quotas;
+mod metrics;
+mod health;
+mod shutdown;
+mod uploads;
+mod sinks;
+mod retention;
+mod routing;
 
 use tonic::transport::Server;
 use std::env;
 use tonic_reflection::server::Builder;
 use crate::parquetb_service::parquetb::parquetb_service_server::ParquetbServiceServer;
-use crate::parquetb_service::MyParquetbService;
+use crate::parquetb_service::{AckSettings, MyParquetbService, StreamErrorPolicy};
+use crate::rules::load_rules::load_rules;
+use crate::offsets::offset_store::OffsetStore;
+use crate::idempotency::batch_store::BatchStore;
+use crate::tenants::tenant_settings::{load_tenant_settings, DEFAULT_TENANT};
+use crate::auth::{auth_interceptor::AuthInterceptor, token_store::TokenStore};
+use crate::tls::server_tls_config::server_tls_config;
+use crate::utils::parse_count::parse_count;
+use crate::utils::parse_interval::parse_interval;
+use crate::metrics::{registry::METRICS, serve_metrics::serve_metrics};
+use crate::health::health_monitor::{spawn_health_monitor, HealthSettings};
+use crate::shutdown::{drain_signal::DrainSignal, wait_for_signal::wait_for_signal};
+use crate::uploads::upload_queue::{UploadQueue, UploadSettings};
+use crate::sinks::sink_registry::SinkRegistry;
+use crate::client::minioc_client::{MiniocClient, MiniocSettings, CHUNK_OVERHEAD};
+use crate::retention::{janitor::{spawn_janitor, JanitorSettings}, retention_policy::RetentionPolicy};
+use crate::routing::routing_table::{load_routing_table, RoutingTable};
 use dotenvy::from_path;
 use std::path::Path;
 use messengerc::{connect_to_messenger_service, MessagingService};
+use std::num::NonZeroU64;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
 #[tokio::main]
@@ -41,21 +78,274 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         eprintln!("Failed to publish message: {:?}", e);
     }
 
-    let parquetb_service = MyParquetbService::default();
+    // Load the optional per-tenant data quality rules
+    let rules = match env::var("PARQUETB_RULES_PATH") {
+        Ok(rules_path) => load_rules(Path::new(&rules_path))?,
+        Err(_) => HashMap::new(),
+    };
+
+    // Load the optional per-tenant ingestion settings
+    let tenant_settings = match env::var("PARQUETB_TENANTS_PATH") {
+        Ok(tenants_path) => load_tenant_settings(Path::new(&tenants_path))?,
+        Err(_) => HashMap::new(),
+    };
+
+    // Configured tenants are metric label values, others are counted as "other"
+    for tenant in rules.keys().chain(tenant_settings.keys().filter(|tenant| *tenant != DEFAULT_TENANT)) {
+        METRICS.register_tenant(tenant);
+    }
+
+    // Load the optional bucket and key prefix of each tenant and table
+    let routing_table = match env::var("PARQUETB_ROUTES_PATH") {
+        Ok(routes_path) => load_routing_table(Path::new(&routes_path))?,
+        Err(_) => RoutingTable::default(),
+    };
+
+    // Decide what happens to the entries of a stream that fails mid-way
+    let stream_error_policy = match env::var("PARQUETB_ON_STREAM_ERROR") {
+        Ok(policy) => policy.parse::<StreamErrorPolicy>()?,
+        Err(_) => StreamErrorPolicy::default(),
+    };
+
+    // Decide how often StreamLogsBidi commits and acknowledges pending rows
+    let mut ack_settings = AckSettings::default();
+    if let Ok(batch_size) = env::var("PARQUETB_ACK_BATCH_SIZE") {
+        ack_settings.batch_size = parse_count("PARQUETB_ACK_BATCH_SIZE", &batch_size)?;
+    }
+    if let Ok(interval) = env::var("PARQUETB_ACK_INTERVAL_SECS") {
+        ack_settings.interval = parse_interval("PARQUETB_ACK_INTERVAL_SECS", &interval)?;
+    }
+
+    // Load the committed sequence numbers of the producers
+    let offsets_path = env::var("PARQUETB_OFFSETS_PATH").unwrap_or_else(|_| "producer_offsets.json".to_string());
+    let offsets = OffsetStore::load(Path::new(&offsets_path))?;
+
+    // Load the batches completed recently, so replayed idempotency keys are recognized
+    let batches_path = env::var("PARQUETB_BATCHES_PATH").unwrap_or_else(|_| "completed_batches.jsonl".to_string());
+    let batches_ttl = match env::var("PARQUETB_BATCHES_TTL_SECS") {
+        Ok(ttl) => Duration::from_secs(ttl.parse()?),
+        Err(_) => Duration::from_secs(24 * 60 * 60),
+    };
+    let batches = BatchStore::load(Path::new(&batches_path), batches_ttl)?;
+
+    // Spool the written files and deliver them in the background, retrying failed uploads
+    let mut upload_settings = UploadSettings::default();
+    if let Ok(spool_dir) = env::var("PARQUETB_SPOOL_DIR") {
+        upload_settings.spool_dir = spool_dir.into();
+    }
+    if let Ok(output_dir) = env::var("PARQUETB_OUTPUT_DIR") {
+        upload_settings.output_dir = output_dir.into();
+    }
+    if let Ok(retention) = env::var("PARQUETB_RETENTION") {
+        upload_settings.retention = retention.parse::<RetentionPolicy>()?;
+    }
+    if let Ok(max_attempts) = env::var("PARQUETB_UPLOAD_MAX_ATTEMPTS") {
+        upload_settings.max_attempts = max_attempts.parse()?;
+    }
+    if let Ok(concurrency) = env::var("PARQUETB_UPLOAD_CONCURRENCY") {
+        upload_settings.max_concurrent_uploads = concurrency.parse()?;
+    }
+    if let Ok(base_backoff) = env::var("PARQUETB_UPLOAD_BASE_BACKOFF_SECS") {
+        upload_settings.base_backoff = Duration::from_secs(base_backoff.parse()?);
+    }
+    if let Ok(max_backoff) = env::var("PARQUETB_UPLOAD_MAX_BACKOFF_SECS") {
+        upload_settings.max_backoff = Duration::from_secs(max_backoff.parse()?);
+    }
+    // Caps the uploads to every sink; MINIOC_MAX_BYTES_PER_SEC is its former name
+    for name in ["MINIOC_MAX_BYTES_PER_SEC", "PARQUETB_UPLOAD_MAX_BYTES_PER_SEC"] {
+        if let Ok(max_bytes_per_sec) = env::var(name) {
+            let max_bytes_per_sec = NonZeroU64::new(max_bytes_per_sec.parse()?).ok_or(format!("{} must be at least 1", name))?;
+            upload_settings.max_bytes_per_sec = Some(max_bytes_per_sec);
+        }
+    }
+
+    // One shared minioc connection for every upload, failing fast while minioc is down
+    let mut minioc_settings = MiniocSettings::default();
+    if let Ok(chunk_size) = env::var("MINIOC_CHUNK_SIZE") {
+        minioc_settings.chunk_size = parse_count("MINIOC_CHUNK_SIZE", &chunk_size)?;
+    }
+    if let Ok(max_message_size) = env::var("MINIOC_MAX_MESSAGE_SIZE") {
+        minioc_settings.max_message_size = parse_count("MINIOC_MAX_MESSAGE_SIZE", &max_message_size)?;
+    }
+    // A chunk too big for minioc would fail every upload of a file larger than it
+    let max_chunk_size = minioc_settings.max_message_size.saturating_sub(CHUNK_OVERHEAD);
+    if minioc_settings.chunk_size > max_chunk_size {
+        return Err(format!(
+            "MINIOC_CHUNK_SIZE must be at most {} bytes to fit in MINIOC_MAX_MESSAGE_SIZE",
+            max_chunk_size
+        ).into());
+    }
+    if let Ok(channel_depth) = env::var("MINIOC_CHANNEL_DEPTH") {
+        minioc_settings.channel_depth = channel_depth.parse()?;
+    }
+    if let Ok(timeout) = env::var("MINIOC_UPLOAD_TIMEOUT_SECS") {
+        minioc_settings.upload_timeout = Duration::from_secs(timeout.parse()?);
+    }
+    if let Ok(min_rate) = env::var("MINIOC_MIN_UPLOAD_BYTES_PER_SEC") {
+        minioc_settings.min_upload_bytes_per_sec =
+            NonZeroU64::new(min_rate.parse()?).ok_or("MINIOC_MIN_UPLOAD_BYTES_PER_SEC must be at least 1")?;
+    }
+    if let Ok(interval) = env::var("MINIOC_KEEPALIVE_INTERVAL_SECS") {
+        minioc_settings.keepalive_interval = Duration::from_secs(interval.parse()?);
+    }
+    if let Ok(timeout) = env::var("MINIOC_KEEPALIVE_TIMEOUT_SECS") {
+        minioc_settings.keepalive_timeout = Duration::from_secs(timeout.parse()?);
+    }
+    if let Ok(threshold) = env::var("MINIOC_BREAKER_THRESHOLD") {
+        minioc_settings.breaker_threshold = threshold.parse()?;
+    }
+    if let Ok(cooldown) = env::var("MINIOC_BREAKER_COOLDOWN_SECS") {
+        minioc_settings.breaker_cooldown = Duration::from_secs(cooldown.parse()?);
+    }
+    if let Ok(require) = env::var("MINIOC_REQUIRE_VERIFICATION") {
+        minioc_settings.require_verification = require.parse()?;
+    }
+    let minioc = MiniocClient::from_env(minioc_settings)?.map(Arc::new);
+
+    // Delivered files past their retention or over the local disk budget get deleted
+    let janitor_settings = JanitorSettings {
+        output_dir: upload_settings.output_dir.clone(),
+        retention: upload_settings.retention,
+        max_bytes: match env::var("PARQUETB_OUTPUT_MAX_BYTES") {
+            Ok(max_bytes) => Some(max_bytes.parse()?),
+            Err(_) => None,
+        },
+        interval: match env::var("PARQUETB_JANITOR_INTERVAL_SECS") {
+            Ok(secs) => parse_interval("PARQUETB_JANITOR_INTERVAL_SECS", &secs)?,
+            Err(_) => Duration::from_secs(60),
+        },
+    };
+    // The output directory defaults to the working directory, which may hold any Parquet file
+    if janitor_settings.deletes_files() && env::var("PARQUETB_OUTPUT_DIR").is_err() {
+        return Err("PARQUETB_OUTPUT_DIR must be set for the janitor to delete files from it".into());
+    }
+
+    let sinks = SinkRegistry::from_tenants(&tenant_settings, minioc)?;
+    let uses_minioc = sinks.uses_minioc();
+    let upload_queue = UploadQueue::open(upload_settings, sinks)?;
+    // Pick up the files a crash or restart left in the spool
+    upload_queue.recover()?;
+    let upload_queue = Arc::new(upload_queue);
+    upload_queue.clone().spawn();
+    spawn_janitor(janitor_settings);
+
+    // How long in-flight calls and uploads get to finish after SIGTERM or SIGINT
+    let drain_timeout = match env::var("PARQUETB_DRAIN_TIMEOUT_SECS") {
+        Ok(secs) => Duration::from_secs(secs.parse()?),
+        Err(_) => Duration::from_secs(30),
+    };
+    let (drain_sender, drain) = DrainSignal::channel();
+
+    let parquetb_service = MyParquetbService::new(rules)
+        .with_stream_error_policy(stream_error_policy)
+        .with_ack_settings(ack_settings)
+        .with_offset_store(offsets)
+        .with_batch_store(batches)
+        .with_tenant_settings(tenant_settings)
+        .with_drain_signal(drain.clone())
+        .with_upload_queue(upload_queue.clone())
+        .with_routing_table(routing_table);
+
+    // Authenticate tenants with their API tokens when a token file is configured
+    let reload_interval = match env::var("PARQUETB_TOKENS_RELOAD_SECS") {
+        Ok(secs) => parse_interval("PARQUETB_TOKENS_RELOAD_SECS", &secs)?,
+        Err(_) => Duration::from_secs(30),
+    };
+    let token_store = match env::var("PARQUETB_TOKENS_PATH") {
+        Ok(tokens_path) => {
+            let token_store = Arc::new(TokenStore::load(Path::new(&tokens_path))?);
+            for tenant in token_store.tenants() {
+                METRICS.register_tenant(&tenant);
+            }
+            token_store.clone().spawn_reload(reload_interval);
+            Some(token_store)
+        }
+        Err(_) => None,
+    };
+
+    // Admin tokens only give access to ListUndeliverableUploads, for every tenant
+    let admin_token_store = match env::var("PARQUETB_ADMIN_TOKENS_PATH") {
+        Ok(tokens_path) => {
+            let admin_token_store = Arc::new(TokenStore::load(Path::new(&tokens_path))?);
+            admin_token_store.clone().spawn_reload(reload_interval);
+            Some(admin_token_store)
+        }
+        Err(_) => None,
+    };
+
+    // Expose the Prometheus metrics when an address is configured
+    if let Ok(metrics_addr) = env::var("PARQUETB_METRICS_ADDR") {
+        let metrics_addr = metrics_addr.parse()?;
+        tokio::spawn(async move {
+            if let Err(e) = serve_metrics(metrics_addr).await {
+                eprintln!("Metrics endpoint stopped: {}", e);
+            }
+        });
+    }
+
+    // Report NOT_SERVING on grpc.health.v1.Health while minioc is unreachable or the disk is nearly full
+    let mut health_settings = HealthSettings {
+        probe_minioc: uses_minioc,
+        disk_paths: vec![upload_queue.spool_dir().to_path_buf(), upload_queue.output_dir().to_path_buf()],
+        ..Default::default()
+    };
+    if let Ok(interval) = env::var("PARQUETB_HEALTH_INTERVAL_SECS") {
+        health_settings.interval = parse_interval("PARQUETB_HEALTH_INTERVAL_SECS", &interval)?;
+    }
+    if let Ok(grace) = env::var("PARQUETB_HEALTH_MINIOC_GRACE_SECS") {
+        health_settings.minioc_grace = Duration::from_secs(grace.parse()?);
+    }
+    if let Ok(min_free) = env::var("PARQUETB_HEALTH_MIN_FREE_PERCENT") {
+        health_settings.min_free_percent = min_free.parse()?;
+    }
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    spawn_health_monitor(health_settings, health_reporter, drain.clone());
 
     println!("{}", &message);
 
     let descriptor_set = include_bytes!(concat!(env!("OUT_DIR"), "/parquetb_descriptor.bin"));
     let reflection_service = Builder::configure()
         .register_encoded_file_descriptor_set(descriptor_set)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
         .build_v1()?;
 
-    // Build and start the gRPC server
-    Server::builder()
-        .add_service(ParquetbServiceServer::new(parquetb_service))
+    // Build and start the gRPC server, with TLS when a certificate is configured
+    let mut server = Server::builder();
+    if let Some(tls) = server_tls_config()? {
+        server = server.tls_config(tls)?;
+    }
+
+    let serve = server
+        .add_service(ParquetbServiceServer::with_interceptor(parquetb_service, AuthInterceptor::new(token_store, admin_token_store)))
+        .add_service(health_service)
         .add_service(reflection_service)
-        .serve(addr)
-        .await?;
+        .serve_with_shutdown(addr, drain.draining());
+    tokio::pin!(serve);
+
+    // On SIGTERM or SIGINT, stop accepting calls and let the running ones commit what they received
+    tokio::select! {
+        result = &mut serve => result?,
+        signal = wait_for_signal() => {
+            signal?;
+            println!("Draining for up to {:?}", drain_timeout);
+            let drain_started = Instant::now();
+            let _ = drain_sender.send(true);
+            // Files committed from now on stay in the spool until the next start
+            upload_queue.stop();
+
+            match tokio::time::timeout(drain_timeout, &mut serve).await {
+                Ok(result) => result?,
+                Err(_) => eprintln!("Drain timeout expired, cutting off the remaining calls"),
+            }
+
+            // Give the running uploads what is left of the timeout
+            let remaining = drain_timeout.saturating_sub(drain_started.elapsed());
+            if !upload_queue.wait_idle(remaining).await {
+                eprintln!("Uploads still running after the drain timeout, they are retried on the next start");
+            }
+            println!("Shutdown complete");
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, Mutex};
+
+use prometheus::{
+    exponential_buckets, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+};
+
+// Metrics of the service, exposed on /metrics
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+// Label standing for the tenants nobody configured or authenticated, and a tenant's tables past
+// the first MAX_TABLE_LABELS, so client-supplied names cannot grow the series without bound
+pub const OTHER_LABEL: &str = "other";
+const MAX_TABLE_LABELS: usize = 100;
+
+pub struct Metrics {
+    pub registry: Registry,
+    // Entries and encoded bytes received, per tenant
+    pub rows_ingested: IntCounterVec,
+    pub bytes_ingested: IntCounterVec,
+    // Duration of the ingestion calls, per RPC
+    pub call_duration: HistogramVec,
+    pub process_logs_duration: Histogram,
+    pub write_parquet_duration: Histogram,
+    pub file_size: Histogram,
+    pub rows_per_file: Histogram,
+    // Entries failing a data quality rule, and those it dropped, per tenant and rule
+    pub rule_failures: IntCounterVec,
+    pub rule_rejections: IntCounterVec,
+    // Tables registered or gained fields, per tenant
+    pub schema_changes: IntCounterVec,
+    pub upload_duration: Histogram,
+    // Failed deliveries, per tenant and destination
+    pub upload_failures: IntCounterVec,
+    // Entries received and not committed yet
+    pub buffered_rows: IntGauge,
+    // Files being delivered to a sink
+    pub uploads_in_flight: IntGauge,
+    // Spooled files waiting for delivery, files out of attempts, and delivered files
+    // still being replicated to the rest of their destinations
+    pub upload_queue_depth: IntGauge,
+    pub undeliverable_files: IntGauge,
+    pub replicating_files: IntGauge,
+    // Tenants allowed as label values, with the tables labelled so far
+    labels: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("parquetb".to_string()), None)
+            .expect("valid metrics prefix");
+        let seconds = || exponential_buckets(0.001, 2.0, 16).unwrap();
+
+        let metrics = Metrics {
+            rows_ingested: IntCounterVec::new(
+                Opts::new("rows_ingested_total", "Log entries received"),
+                &["tenant"],
+            ).unwrap(),
+            bytes_ingested: IntCounterVec::new(
+                Opts::new("bytes_ingested_total", "Encoded bytes of the log entries received"),
+                &["tenant"],
+            ).unwrap(),
+            call_duration: HistogramVec::new(
+                HistogramOpts::new("call_duration_seconds", "Duration of the ingestion calls")
+                    .buckets(exponential_buckets(0.01, 2.0, 16).unwrap()),
+                &["rpc"],
+            ).unwrap(),
+            process_logs_duration: Histogram::with_opts(
+                HistogramOpts::new("process_logs_duration_seconds", "Time to turn a batch into a Parquet file")
+                    .buckets(seconds()),
+            ).unwrap(),
+            write_parquet_duration: Histogram::with_opts(
+                HistogramOpts::new("write_parquet_duration_seconds", "Time to write a Parquet file")
+                    .buckets(seconds()),
+            ).unwrap(),
+            file_size: Histogram::with_opts(
+                HistogramOpts::new("file_size_bytes", "Size of the Parquet files written")
+                    .buckets(exponential_buckets(1024.0, 4.0, 12).unwrap()),
+            ).unwrap(),
+            rows_per_file: Histogram::with_opts(
+                HistogramOpts::new("rows_per_file", "Rows of the Parquet files written")
+                    .buckets(exponential_buckets(1.0, 4.0, 12).unwrap()),
+            ).unwrap(),
+            rule_failures: IntCounterVec::new(
+                Opts::new("rule_failures_total", "Log entries failing a data quality rule"),
+                &["tenant", "rule"],
+            ).unwrap(),
+            rule_rejections: IntCounterVec::new(
+                Opts::new("rule_rejections_total", "Log entries dropped by a data quality rule"),
+                &["tenant", "rule"],
+            ).unwrap(),
+            schema_changes: IntCounterVec::new(
+                Opts::new("schema_changes_total", "Table schemas registered or extended"),
+                &["tenant", "table"],
+            ).unwrap(),
+            upload_duration: Histogram::with_opts(
+                HistogramOpts::new("upload_duration_seconds", "Time to deliver a file to its sink")
+                    .buckets(seconds()),
+            ).unwrap(),
+            upload_failures: IntCounterVec::new(
+                Opts::new("upload_failures_total", "Failed deliveries to a sink"),
+                &["tenant", "destination"],
+            ).unwrap(),
+            buffered_rows: IntGauge::new("buffered_rows", "Log entries received and not committed yet").unwrap(),
+            uploads_in_flight: IntGauge::new("uploads_in_flight", "Files being delivered to a sink").unwrap(),
+            upload_queue_depth: IntGauge::new("upload_queue_depth", "Spooled files waiting for delivery").unwrap(),
+            undeliverable_files: IntGauge::new("undeliverable_files", "Spooled files out of delivery attempts").unwrap(),
+            replicating_files: IntGauge::new("replicating_files", "Delivered files still sent to the rest of their destinations").unwrap(),
+            registry,
+            labels: Mutex::new(HashMap::new()),
+        };
+
+        let registry = &metrics.registry;
+        registry.register(Box::new(metrics.rows_ingested.clone())).unwrap();
+        registry.register(Box::new(metrics.bytes_ingested.clone())).unwrap();
+        registry.register(Box::new(metrics.call_duration.clone())).unwrap();
+        registry.register(Box::new(metrics.process_logs_duration.clone())).unwrap();
+        registry.register(Box::new(metrics.write_parquet_duration.clone())).unwrap();
+        registry.register(Box::new(metrics.file_size.clone())).unwrap();
+        registry.register(Box::new(metrics.rows_per_file.clone())).unwrap();
+        registry.register(Box::new(metrics.rule_failures.clone())).unwrap();
+        registry.register(Box::new(metrics.rule_rejections.clone())).unwrap();
+        registry.register(Box::new(metrics.schema_changes.clone())).unwrap();
+        registry.register(Box::new(metrics.upload_duration.clone())).unwrap();
+        registry.register(Box::new(metrics.upload_failures.clone())).unwrap();
+        registry.register(Box::new(metrics.buffered_rows.clone())).unwrap();
+        registry.register(Box::new(metrics.uploads_in_flight.clone())).unwrap();
+        registry.register(Box::new(metrics.upload_queue_depth.clone())).unwrap();
+        registry.register(Box::new(metrics.undeliverable_files.clone())).unwrap();
+        registry.register(Box::new(metrics.replicating_files.clone())).unwrap();
+        metrics
+    }
+}
+
+impl Metrics {
+    // Let the tenant appear as a label value from now on
+    pub fn register_tenant(&self, tenant: &str) {
+        let mut labels = self.labels.lock().unwrap();
+        if !labels.contains_key(tenant) {
+            labels.insert(tenant.to_string(), HashSet::new());
+        }
+    }
+
+    pub fn tenant_label<'a>(&self, tenant: &'a str) -> &'a str {
+        if self.labels.lock().unwrap().contains_key(tenant) {
+            tenant
+        } else {
+            OTHER_LABEL
+        }
+    }
+
+    pub fn table_labels<'a>(&self, tenant: &'a str, table: &'a str) -> [&'a str; 2] {
+        let mut labels = self.labels.lock().unwrap();
+        let Some(tables) = labels.get_mut(tenant) else {
+            return [OTHER_LABEL, OTHER_LABEL];
+        };
+        if tables.contains(table) || tables.len() < MAX_TABLE_LABELS && tables.insert(table.to_string()) {
+            [tenant, table]
+        } else {
+            [tenant, OTHER_LABEL]
+        }
+    }
+}
+
+// Entries of one stream counted in the buffered_rows gauge, released when committed or dropped
+#[derive(Debug, Default)]
+pub struct BufferedRows {
+    count: i64,
+}
+
+impl BufferedRows {
+    pub fn add(&mut self, rows: usize) {
+        self.count += rows as i64;
+        METRICS.buffered_rows.add(rows as i64);
+    }
+
+    pub fn clear(&mut self) {
+        METRICS.buffered_rows.sub(self.count);
+        self.count = 0;
+    }
+}
+
+impl Drop for BufferedRows {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+// Holds one unit of a gauge until dropped, so cancelled work is released too
+pub struct GaugeGuard {
+    gauge: IntGauge,
+}
+
+impl GaugeGuard {
+    pub fn new(gauge: &IntGauge) -> Self {
+        gauge.inc();
+        GaugeGuard { gauge: gauge.clone() }
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
@@ -0,0 +1,58 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use prometheus::{Encoder, TextEncoder};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, error};
+
+use crate::metrics::registry::METRICS;
+
+// Time a scraper gets to send its request before the connection is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Serve the metrics in the Prometheus text format on GET /metrics
+pub async fn serve_metrics(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics on http://{}/metrics", addr);
+
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = answer_scrape(socket).await {
+                error!("Failed to answer metrics request from {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+// Answer one HTTP request and close the connection
+async fn answer_scrape(mut socket: TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut request = vec![0u8; 4096];
+    let read = tokio::time::timeout(READ_TIMEOUT, socket.read(&mut request))
+        .await
+        .map_err(|_| "timed out reading the request")??;
+    let request_line = String::from_utf8_lossy(&request[..read]);
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status, content_type, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        let encoder = TextEncoder::new();
+        let mut body = Vec::new();
+        encoder.encode(&METRICS.registry.gather(), &mut body)?;
+        ("200 OK", encoder.format_type().to_string(), body)
+    } else {
+        ("404 Not Found", "text/plain".to_string(), b"Not Found\n".to_vec())
+    };
+
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    socket.write_all(header.as_bytes()).await?;
+    socket.write_all(&body).await?;
+    socket.shutdown().await?;
+    Ok(())
+}
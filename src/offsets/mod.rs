@@ -0,0 +1 @@
+pub mod offset_store;
@@ -0,0 +1,240 @@
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_json::Value;
+use tracing::{error, info};
+
+use crate::parquetb_error::ParquetbError;
+
+// High-water mark of the committed sequence numbers of each producer, persisted as JSON
+#[derive(Debug, Default)]
+pub struct OffsetStore {
+    path: Option<PathBuf>,
+    state: Mutex<Offsets>,
+}
+
+#[derive(Debug, Default)]
+struct Offsets {
+    committed: HashMap<String, u64>,
+    // Producers with a batch being written, which no other call may write for meanwhile
+    reserved: HashSet<String>,
+}
+
+// Entries left after removing the ones already committed. Their producers stay reserved until
+// the marks are committed; dropping the entries uncommitted releases them.
+pub struct NewEntries<'a> {
+    pub log_entries: Vec<Value>,
+    pub skipped: usize,
+    store: &'a OffsetStore,
+    // Highest sequence number seen per producer, to commit once the entries are durable
+    marks: HashMap<String, u64>,
+}
+
+impl NewEntries<'_> {
+    // Record the new high-water marks and persist them
+    pub fn commit(mut self) {
+        let marks = std::mem::take(&mut self.marks);
+        if marks.is_empty() {
+            return;
+        }
+
+        let mut state = self.store.state.lock().unwrap();
+        for (key, sequence) in marks {
+            state.reserved.remove(&key);
+            let mark = state.committed.entry(key).or_insert(sequence);
+            *mark = (*mark).max(sequence);
+        }
+
+        if let Some(path) = &self.store.path {
+            if let Err(e) = persist(path, &state.committed) {
+                error!("Failed to persist committed offsets to {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+impl Drop for NewEntries<'_> {
+    fn drop(&mut self) {
+        if self.marks.is_empty() {
+            return;
+        }
+        let mut state = self.store.state.lock().unwrap();
+        for key in self.marks.keys() {
+            state.reserved.remove(key);
+        }
+    }
+}
+
+impl OffsetStore {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let committed = if path.exists() {
+            let content = std::fs::read_to_string(path)?;
+            serde_json::from_str::<HashMap<String, u64>>(&content)?
+        } else {
+            HashMap::new()
+        };
+        info!("Loaded committed offsets of {} producers from {:?}", committed.len(), path);
+
+        Ok(OffsetStore {
+            path: Some(path.to_path_buf()),
+            state: Mutex::new(Offsets { committed, ..Default::default() }),
+        })
+    }
+
+    fn key(tenant: &str, producer_id: &str) -> String {
+        format!("{}/{}", tenant, producer_id)
+    }
+
+    pub fn committed(&self, tenant: &str, producer_id: &str) -> Option<u64> {
+        self.state.lock().unwrap().committed.get(&Self::key(tenant, producer_id)).copied()
+    }
+
+    // Drop the entries whose sequence number is not above the producer's committed mark, and
+    // reserve the producers of the others until they are committed. Entries without a producer
+    // ID are always kept. Fails when a producer's sequence numbers are 0, the proto3 default that
+    // cannot be told from unset, or go backwards within the batch, or when another call is still
+    // writing for one of the producers.
+    pub fn filter_new(&self, log_entries: &[Value]) -> Result<NewEntries<'_>, ParquetbError> {
+        let mut state = self.state.lock().unwrap();
+        let mut marks: HashMap<String, u64> = HashMap::new();
+        let mut kept = Vec::with_capacity(log_entries.len());
+        let mut skipped = 0;
+
+        for log_entry in log_entries {
+            let producer_id = log_entry["producer_id"].as_str().unwrap_or_default();
+            if producer_id.is_empty() {
+                kept.push(log_entry.clone());
+                continue;
+            }
+
+            let tenant = log_entry["tenant_name"].as_str().unwrap_or_default();
+            let key = Self::key(tenant, producer_id);
+            let sequence = log_entry["sequence"].as_u64().unwrap_or_default();
+            if sequence == 0 {
+                return Err(ParquetbError::Validation(format!(
+                    "Entries of producer {} need a sequence number of at least 1",
+                    producer_id
+                )));
+            }
+
+            if let Some(previous) = marks.get(&key) {
+                if sequence < *previous {
+                    return Err(ParquetbError::Validation(format!(
+                        "Entries of producer {} are out of order: sequence {} after {}",
+                        producer_id, sequence, previous
+                    )));
+                }
+                if sequence == *previous {
+                    skipped += 1;
+                    continue;
+                }
+            } else if state.committed.get(&key).is_some_and(|mark| sequence <= *mark) {
+                skipped += 1;
+                continue;
+            }
+
+            marks.insert(key, sequence);
+            kept.push(log_entry.clone());
+        }
+
+        if let Some(key) = marks.keys().find(|key| state.reserved.contains(*key)) {
+            return Err(ParquetbError::Conflict(format!(
+                "Another call is still writing entries of producer {}",
+                key
+            )));
+        }
+        state.reserved.extend(marks.keys().cloned());
+
+        if skipped > 0 {
+            info!("Skipped {} entries already committed by their producers.", skipped);
+        }
+
+        Ok(NewEntries { log_entries: kept, skipped, store: self, marks })
+    }
+}
+
+fn persist(path: &Path, marks: &HashMap<String, u64>) -> Result<(), Box<dyn Error>> {
+    // Write to a temporary file first so a crash never leaves a truncated offsets file
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec(marks)?)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(producer_id: &str, sequence: u64) -> Value {
+        json!({ "tenant_name": "acme", "producer_id": producer_id, "sequence": sequence })
+    }
+
+    #[test]
+    fn commit_skips_entries_at_or_below_the_mark() {
+        let store = OffsetStore::default();
+        let new_entries = store.filter_new(&[entry("p1", 1), entry("p1", 2)]).unwrap();
+        assert_eq!(new_entries.log_entries.len(), 2);
+        new_entries.commit();
+        assert_eq!(store.committed("acme", "p1"), Some(2));
+
+        let new_entries = store.filter_new(&[entry("p1", 2), entry("p1", 3)]).unwrap();
+        assert_eq!(new_entries.log_entries, vec![entry("p1", 3)]);
+        assert_eq!(new_entries.skipped, 1);
+    }
+
+    #[test]
+    fn reserved_producer_conflicts() {
+        let store = OffsetStore::default();
+        let _first = store.filter_new(&[entry("p1", 1)]).unwrap();
+        assert!(matches!(store.filter_new(&[entry("p1", 2)]), Err(ParquetbError::Conflict(_))));
+        assert!(store.filter_new(&[entry("p2", 1)]).is_ok());
+    }
+
+    #[test]
+    fn drop_releases_without_committing() {
+        let store = OffsetStore::default();
+        drop(store.filter_new(&[entry("p1", 1)]).unwrap());
+        assert_eq!(store.committed("acme", "p1"), None);
+
+        let new_entries = store.filter_new(&[entry("p1", 1)]).unwrap();
+        assert_eq!(new_entries.log_entries.len(), 1);
+    }
+
+    #[test]
+    fn failed_call_reserves_nothing() {
+        let store = OffsetStore::default();
+        assert!(store.filter_new(&[entry("p1", 2), entry("p1", 1)]).is_err());
+        assert!(store.filter_new(&[entry("p1", 1)]).is_ok());
+    }
+
+    #[test]
+    fn repeated_sequence_within_a_call_is_skipped() {
+        let store = OffsetStore::default();
+        let new_entries = store.filter_new(&[entry("p1", 1), entry("p1", 1)]).unwrap();
+        assert_eq!(new_entries.log_entries.len(), 1);
+        assert_eq!(new_entries.skipped, 1);
+    }
+
+    #[test]
+    fn rejects_out_of_order_and_zero_sequences() {
+        let store = OffsetStore::default();
+        assert!(matches!(
+            store.filter_new(&[entry("p1", 3), entry("p1", 2)]),
+            Err(ParquetbError::Validation(_))
+        ));
+        assert!(matches!(store.filter_new(&[entry("p1", 0)]), Err(ParquetbError::Validation(_))));
+    }
+
+    #[test]
+    fn keeps_entries_without_producer() {
+        let store = OffsetStore::default();
+        let log_entries = vec![json!({ "tenant_name": "acme" }), json!({ "tenant_name": "acme", "sequence": 0 })];
+        let new_entries = store.filter_new(&log_entries).unwrap();
+        assert_eq!(new_entries.log_entries, log_entries);
+        assert!(new_entries.marks.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use prost::Message;
+use tonic::codegen::Bytes;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Status};
+
+// Minimal copies of the google.rpc messages used for gRPC error details
+pub mod google_rpc {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Any {
+        #[prost(string, tag = "1")]
+        pub type_url: String,
+        #[prost(bytes = "vec", tag = "2")]
+        pub value: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Status {
+        #[prost(int32, tag = "1")]
+        pub code: i32,
+        #[prost(string, tag = "2")]
+        pub message: String,
+        #[prost(message, repeated, tag = "3")]
+        pub details: Vec<Any>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ErrorInfo {
+        #[prost(string, tag = "1")]
+        pub reason: String,
+        #[prost(string, tag = "2")]
+        pub domain: String,
+        #[prost(map = "string, string", tag = "3")]
+        pub metadata: HashMap<String, String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Duration {
+        #[prost(int64, tag = "1")]
+        pub seconds: i64,
+        #[prost(int32, tag = "2")]
+        pub nanos: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RetryInfo {
+        #[prost(message, optional, tag = "1")]
+        pub retry_delay: Option<Duration>,
+    }
+}
+
+const ERROR_DOMAIN: &str = "parquetb";
+
+#[derive(Debug)]
+pub enum ParquetbError {
+    // The client sent something we cannot accept
+    Validation(String),
+    // The entries do not fit the schema being written
+    SchemaConflict(String),
+    // Writing the Parquet file or queuing it locally failed
+    Storage(String),
+    // The tenant went over one of its limits, and may retry after the given delay
+    Quota(String, Duration),
+    // The caller is not allowed to write for this tenant
+    Unauthorized(String),
+    // Another call is writing the same entries, the caller may retry once it is done
+    Conflict(String),
+}
+
+impl ParquetbError {
+    pub fn code(&self) -> Code {
+        match self {
+            ParquetbError::Validation(_) => Code::InvalidArgument,
+            ParquetbError::SchemaConflict(_) => Code::FailedPrecondition,
+            ParquetbError::Storage(_) => Code::Internal,
+            ParquetbError::Quota(..) => Code::ResourceExhausted,
+            ParquetbError::Unauthorized(_) => Code::PermissionDenied,
+            ParquetbError::Conflict(_) => Code::Aborted,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ParquetbError::Validation(_) => "VALIDATION_FAILED",
+            ParquetbError::SchemaConflict(_) => "SCHEMA_CONFLICT",
+            ParquetbError::Storage(_) => "STORAGE_FAILED",
+            ParquetbError::Quota(..) => "QUOTA_EXCEEDED",
+            ParquetbError::Unauthorized(_) => "TENANT_NOT_AUTHORIZED",
+            ParquetbError::Conflict(_) => "CONCURRENT_WRITE",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            ParquetbError::Validation(message)
+            | ParquetbError::SchemaConflict(message)
+            | ParquetbError::Storage(message)
+            | ParquetbError::Quota(message, _)
+            | ParquetbError::Unauthorized(message)
+            | ParquetbError::Conflict(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for ParquetbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.reason(), self.message())
+    }
+}
+
+impl std::error::Error for ParquetbError {}
+
+impl From<ParquetbError> for Status {
+    fn from(error: ParquetbError) -> Self {
+        let error_info = google_rpc::ErrorInfo {
+            reason: error.reason().to_string(),
+            domain: ERROR_DOMAIN.to_string(),
+            metadata: HashMap::new(),
+        };
+
+        let mut details = google_rpc::Status {
+            code: error.code() as i32,
+            message: error.message().to_string(),
+            details: vec![google_rpc::Any {
+                type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+                value: error_info.encode_to_vec(),
+            }],
+        };
+
+        let retry_after = match &error {
+            ParquetbError::Quota(_, retry_after) => Some(*retry_after),
+            _ => None,
+        };
+        if let Some(retry_after) = retry_after {
+            let retry_info = google_rpc::RetryInfo {
+                retry_delay: Some(google_rpc::Duration {
+                    seconds: retry_after.as_secs() as i64,
+                    nanos: retry_after.subsec_nanos() as i32,
+                }),
+            };
+            details.details.push(google_rpc::Any {
+                type_url: "type.googleapis.com/google.rpc.RetryInfo".to_string(),
+                value: retry_info.encode_to_vec(),
+            });
+        }
+
+        let mut status = Status::with_details(
+            error.code(),
+            error.message().to_string(),
+            Bytes::from(details.encode_to_vec()),
+        );
+        // Also as a plain header, for clients that do not decode error details
+        if let Some(retry_after) = retry_after {
+            let seconds = retry_after.as_secs_f64().ceil() as u64;
+            status.metadata_mut().insert("retry-after", MetadataValue::from(seconds));
+        }
+        status
+    }
+}
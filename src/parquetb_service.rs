@@ -1,25 +1,407 @@
 
 use tonic::{Request, Response, Status, Streaming};
+use tonic::metadata::MetadataMap;
 use tonic::async_trait;
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
 
 pub mod parquetb {
     tonic::include_proto!("parquetb");
 }
 
 use parquetb::parquetb_service_server::ParquetbService;
-use parquetb::{LogEntry, UploadResponse};
+use parquetb::{CommittedOffsetRequest, CommittedOffsetResponse, LogAck, LogEntry, UploadResponse, WriteBatchRequest};
+use parquetb::{DestinationStatus, ListUndeliverableUploadsRequest, ListUndeliverableUploadsResponse, UndeliverableUpload};
 
 use crate::utils::{build_schema::build_schema, log_entry_to_arrays::log_entry_to_arrays, write_parquet_file::write_parquet_file};
-use crate::client::send_log::send_log;
-use serde_json::json;
+use crate::utils::log_entry_to_value::log_entry_to_value;
+use crate::utils::reserve_file_name::{reserve_file_name, ReservedFile};
+use crate::rules::{apply_rules::apply_rules, quality_rule::QualityRule};
+use crate::parquetb_error::ParquetbError;
+use crate::offsets::offset_store::OffsetStore;
+use crate::idempotency::batch_store::{BatchStatus, BatchStore, IDEMPOTENCY_KEY_HEADER};
+use crate::tenants::tenant_settings::TenantSettings;
+use crate::dedup::deduplicator::{DedupReservation, Deduplicator};
+use crate::ingest::ingest_options::IngestOptions;
+use crate::auth::token_store::{AdminCaller, AuthorizedTenant};
+use crate::tables::schema_registry::SchemaRegistry;
+use crate::utils::merge_schemas::merge_schemas;
+use crate::quotas::quota_manager::{QuotaManager, StreamPermit, TenantLimits};
+use crate::metrics::registry::{BufferedRows, METRICS};
+use crate::shutdown::drain_signal::DrainSignal;
+use crate::uploads::{spooled_file::spool_metadata, upload_queue::UploadQueue};
+use crate::routing::{key_template::KeyContext, object_location::ObjectLocation, routing_table::RoutingTable};
+use prost::Message;
 // use arrow::datatypes::Schema;
-use std::error::Error;
+use std::collections::HashMap;
+use std::str::FromStr;
+use arrow::array::{Array, ArrayRef};
+use arrow::compute::concat;
 use tracing::{info, error};
 
-#[derive(Debug, Default)]
-pub struct MyParquetbService;
+// What to do with the entries already received when the client stream fails mid-way
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StreamErrorPolicy {
+    // Persist and upload the entries received before the failure
+    Commit,
+    // Discard everything received on the failed stream
+    #[default]
+    Rollback,
+}
+
+impl FromStr for StreamErrorPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "commit" => Ok(StreamErrorPolicy::Commit),
+            "rollback" => Ok(StreamErrorPolicy::Rollback),
+            other => Err(format!("Unknown stream error policy: {}", other)),
+        }
+    }
+}
+
+// When StreamLogsBidi commits the pending rows and acknowledges them
+#[derive(Debug, Clone, Copy)]
+pub struct AckSettings {
+    // Commit as soon as this many rows are pending
+    pub batch_size: usize,
+    // Commit whatever is pending at least this often
+    pub interval: Duration,
+}
+
+impl Default for AckSettings {
+    fn default() -> Self {
+        AckSettings {
+            batch_size: 1000,
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MyParquetbService {
+    rules: Arc<HashMap<String, Vec<QualityRule>>>,
+    stream_error_policy: StreamErrorPolicy,
+    ack_settings: AckSettings,
+    offsets: Arc<OffsetStore>,
+    batches: Arc<BatchStore>,
+    tenants: Arc<HashMap<String, TenantSettings>>,
+    dedup: Arc<Deduplicator>,
+    schemas: Arc<SchemaRegistry>,
+    quotas: Arc<QuotaManager>,
+    drain: DrainSignal,
+    uploads: Arc<UploadQueue>,
+    routes: Arc<RoutingTable>,
+}
+
+impl MyParquetbService {
+    pub fn new(rules: HashMap<String, Vec<QualityRule>>) -> Self {
+        MyParquetbService {
+            rules: Arc::new(rules),
+            stream_error_policy: StreamErrorPolicy::default(),
+            ack_settings: AckSettings::default(),
+            offsets: Arc::new(OffsetStore::default()),
+            batches: Arc::new(BatchStore::default()),
+            tenants: Arc::new(HashMap::new()),
+            dedup: Arc::new(Deduplicator::default()),
+            schemas: Arc::new(SchemaRegistry::default()),
+            quotas: Arc::new(QuotaManager::default()),
+            drain: DrainSignal::default(),
+            uploads: Arc::new(UploadQueue::default()),
+            routes: Arc::new(RoutingTable::default()),
+        }
+    }
+
+    pub fn with_stream_error_policy(mut self, policy: StreamErrorPolicy) -> Self {
+        self.stream_error_policy = policy;
+        self
+    }
+
+    pub fn with_ack_settings(mut self, ack_settings: AckSettings) -> Self {
+        self.ack_settings = ack_settings;
+        self
+    }
+
+    pub fn with_offset_store(mut self, offsets: OffsetStore) -> Self {
+        self.offsets = Arc::new(offsets);
+        self
+    }
+
+    pub fn with_batch_store(mut self, batches: BatchStore) -> Self {
+        self.batches = Arc::new(batches);
+        self
+    }
+
+    pub fn with_tenant_settings(mut self, tenants: HashMap<String, TenantSettings>) -> Self {
+        self.tenants = Arc::new(tenants);
+        self
+    }
+
+    pub fn with_drain_signal(mut self, drain: DrainSignal) -> Self {
+        self.drain = drain;
+        self
+    }
+
+    pub fn with_upload_queue(mut self, uploads: Arc<UploadQueue>) -> Self {
+        self.uploads = uploads;
+        self
+    }
+
+    pub fn with_routing_table(mut self, routes: RoutingTable) -> Self {
+        self.routes = Arc::new(routes);
+        self
+    }
+
+    fn tenant_limits(&self, tenant: &str) -> Option<&TenantLimits> {
+        self.tenants.get(tenant).and_then(|tenant| tenant.limits.as_ref())
+    }
+
+    // Tenants named by a token or by the server's settings. Others are whatever an
+    // unauthenticated caller sent and are counted under the "other" metric label.
+    fn is_known_tenant(&self, tenant: &str, options: &IngestOptions) -> bool {
+        options.authorized || self.tenants.contains_key(tenant) || self.rules.contains_key(tenant)
+    }
+
+    // Charge resolved entries against the tenant's limits, taking one of its
+    // concurrent streams on the first entry
+    fn admit_entries(&self, entries: &[LogEntry], options: &IngestOptions, permit: &mut Option<StreamPermit>) -> Result<(), ParquetbError> {
+        let Some(tenant) = entries.first().map(|entry| entry.tenant_name.as_str()) else {
+            return Ok(());
+        };
+        let bytes = entries.iter().map(|entry| entry.encoded_len()).sum();
+
+        if let Some(limits) = self.tenant_limits(tenant) {
+            if permit.is_none() {
+                *permit = Some(self.quotas.acquire_stream(tenant, limits)?);
+            }
+            self.quotas.admit(tenant, limits, entries.len(), bytes)?;
+        }
+
+        if self.is_known_tenant(tenant, options) {
+            METRICS.register_tenant(tenant);
+        }
+        let label = METRICS.tenant_label(tenant);
+        METRICS.rows_ingested.with_label_values(&[label]).inc_by(entries.len() as u64);
+        METRICS.bytes_ingested.with_label_values(&[label]).inc_by(bytes as u64);
+        Ok(())
+    }
+
+    // Write the entries to a Parquet file and upload it
+    async fn commit_entries(&self, log_entries: &[serde_json::Value], options: &IngestOptions) -> Result<ProcessedLogs<'_>, ParquetbError> {
+        // Skip the entries their producers already got committed
+        let new_entries = self.offsets.filter_new(log_entries)?;
+        if new_entries.log_entries.is_empty() {
+            info!("All {} entries were already committed, nothing to write.", new_entries.skipped);
+            return Ok(ProcessedLogs {
+                tenant_name: log_entries[0]["tenant_name"].as_str().unwrap_or_default().to_string(),
+                rows_received: log_entries.len(),
+                rows_skipped: new_entries.skipped,
+                ..Default::default()
+            });
+        }
+
+        let mut processed = self.process_logs(&new_entries.log_entries, options).await?;
+        processed.rows_received = log_entries.len();
+        processed.rows_skipped = new_entries.skipped;
+
+        // Queue the Parquet file for upload, unless every entry was a duplicate
+        if processed.rows_written > 0 {
+            let file_name = &processed.file_name;
+            if let Err(e) = self.uploads.enqueue(file_name, &processed.tenant_name, &processed.location) {
+                error!("Failed to queue {} for upload: {}", file_name, e);
+                return Err(ParquetbError::Storage(format!("Error queuing parquetb file: {}", e)));
+            }
+        }
+
+        // The entries are durable locally, producers can now resume after them
+        if let Some(spool_file) = processed.spool_file.take() {
+            spool_file.keep();
+        }
+        new_entries.commit();
+        if let Some(dedup) = processed.dedup.take() {
+            dedup.keep();
+        }
+
+        Ok(processed)
+    }
+
+    // Read a client stream to the end and commit its entries
+    async fn ingest_stream(&self, mut stream: Streaming<LogEntry>, mut options: IngestOptions) -> Result<UploadResponse, Status> {
+        let mut log_entries = vec![];
+        let mut interrupted = None;
+        let mut permit = None;
+        let mut buffered = BufferedRows::default();
+        let mut drained = false;
+
+        // Process the incoming stream of log entries, until it ends or the server drains
+        loop {
+            let log_entry = tokio::select! {
+                log_entry = stream.next() => log_entry,
+                _ = self.drain.draining() => {
+                    info!("Server is draining, committing the {} entries received so far.", log_entries.len());
+                    drained = true;
+                    break;
+                }
+            };
+            let Some(log_entry) = log_entry else {
+                break;
+            };
+
+            match log_entry {
+                Ok(mut entry) => {
+                    options.resolve_entry(&mut entry)?;
+                    self.admit_entries(std::slice::from_ref(&entry), &options, &mut permit)?;
+                    log_entries.push(log_entry_to_value(entry));
+                    buffered.add(1);
+                }
+                Err(status) => {
+                    error!("Error reading stream after {} entries: {}", log_entries.len(), status);
+                    interrupted = Some(status);
+                    break;
+                }
+            }
+        }
+
+        if let Some(status) = &interrupted {
+            if self.stream_error_policy == StreamErrorPolicy::Rollback || log_entries.is_empty() {
+                return Err(Status::aborted(format!(
+                    "Error reading stream, {} received entries were discarded: {}",
+                    log_entries.len(),
+                    status.message()
+                )));
+            }
+            info!("Committing the {} entries received before the stream failed.", log_entries.len());
+        }
+
+        if log_entries.is_empty() {
+            if drained {
+                return Err(Status::unavailable("Server is shutting down"));
+            }
+            return Err(Status::invalid_argument("No log entries provided"));
+        }
+
+        // Process the log entries, generate the Parquet file and upload it
+        let processed = self.commit_entries(&log_entries, &options).await?;
+
+        Ok(upload_response(&processed, interrupted.is_some() || drained))
+    }
+
+    // Commit the entries of a unary WriteBatch call
+    async fn ingest_batch(&self, batch: WriteBatchRequest, mut options: IngestOptions) -> Result<UploadResponse, Status> {
+        if batch.entries.is_empty() {
+            return Err(Status::invalid_argument("No log entries provided"));
+        }
+
+        // The batch tenant, table and write options are more defaults, and must agree with the headers
+        let mut batch_entry = LogEntry { tenant_name: batch.tenant_name, table: batch.table, ..Default::default() };
+        options.resolve_entry(&mut batch_entry)?;
+        options.resolve_write_options(&batch.compression, batch.max_row_group_size)?;
+
+        // Entries inherit the batch tenant and must not name another one
+        let mut entries = batch.entries;
+        for entry in entries.iter_mut() {
+            options.resolve_entry(entry)?;
+        }
+
+        let mut permit = None;
+        self.admit_entries(&entries, &options, &mut permit)?;
+        let log_entries: Vec<_> = entries.into_iter().map(log_entry_to_value).collect();
+        info!("Received a batch of {} entries for tenant {:?}", log_entries.len(), options.tenant);
+
+        let processed = self.commit_entries(&log_entries, &options).await?;
+
+        Ok(upload_response(&processed, false))
+    }
+
+    // Run an ingestion at most once per idempotency key, replaying the response of a completed one
+    async fn run_idempotent(
+        &self,
+        idempotency_key: Option<String>,
+        ingestion: impl Future<Output = Result<UploadResponse, Status>>,
+    ) -> Result<UploadResponse, Status> {
+        let Some(key) = idempotency_key else {
+            return ingestion.await;
+        };
+
+        match self.batches.begin(&key) {
+            BatchStatus::Completed(response) => {
+                info!("Batch {} was already completed, returning its original response.", key);
+                Ok(response)
+            }
+            BatchStatus::InFlight => Err(Status::aborted(format!(
+                "A batch with idempotency key {} is already being processed",
+                key
+            ))),
+            BatchStatus::Started(batch) => {
+                let response = ingestion.await?;
+                // A partial commit is recorded too: a retry must not write its rows again, and
+                // gets the response telling how many rows were kept
+                batch.complete(&response);
+                Ok(response)
+            }
+        }
+    }
+
+    // Commit one chunk of a bidirectional stream and build its acknowledgement
+    async fn commit_chunk(&self, log_entries: &[serde_json::Value], options: &IngestOptions, first_row: u64) -> Result<LogAck, Status> {
+        let processed = self.commit_entries(log_entries, options).await?;
+        let row_count = log_entries.len() as u64;
+
+        info!("Acknowledging rows {}..{} written to {}", first_row, first_row + row_count, processed.file_name);
+        Ok(LogAck {
+            first_row,
+            row_count,
+            file_name: processed.file_name,
+            rows_written: processed.rows_written as u64,
+            rows_rejected: processed.rows_rejected as u64,
+            rows_skipped: processed.rows_skipped as u64,
+            duplicates_dropped: processed.duplicates_dropped as u64,
+            committed_offset: first_row + row_count,
+            object_key: processed.location.key,
+        })
+    }
+}
+
+// Stream of acknowledgements sent back on StreamLogsBidi
+pub struct AckStream {
+    receiver: mpsc::Receiver<Result<LogAck, Status>>,
+}
+
+impl Stream for AckStream {
+    type Item = Result<LogAck, Status>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+// Outcome of turning a batch of log entries into a Parquet file
+#[derive(Default)]
+pub struct ProcessedLogs<'a> {
+    pub tenant_name: String,
+    pub file_name: String,
+    // Bucket and key the file is uploaded under
+    pub location: ObjectLocation,
+    pub rows_written: usize,
+    // Entries consumed from the call, whatever became of them
+    pub rows_received: usize,
+    pub rows_rejected: usize,
+    // Entries dropped because their sequence number was already committed
+    pub rows_skipped: usize,
+    // Entries dropped because their natural key was already seen
+    pub duplicates_dropped: usize,
+    // Natural keys of the written entries, forgotten again unless they are committed
+    pub dedup: Option<DedupReservation<'a>>,
+    // The written file, removed from the spool again unless it is queued
+    pub spool_file: Option<ReservedFile>,
+}
 
 #[async_trait]
 impl ParquetbService for MyParquetbService {
@@ -27,102 +409,434 @@ impl ParquetbService for MyParquetbService {
         &self,
         request: Request<Streaming<LogEntry>>,
     ) -> Result<Response<UploadResponse>, Status> {
+        let _timer = METRICS.call_duration.with_label_values(&["StreamLogs"]).start_timer();
+        // An optional client-supplied batch ID makes retries safe
+        let options = IngestOptions::from_request(&request)?;
+        let idempotency_key = idempotency_key(request.metadata())?
+            .map(|key| scoped_idempotency_key(options.tenant.as_deref(), key))
+            .transpose()?;
+        let stream = request.into_inner();
+
+        self.run_idempotent(idempotency_key, self.ingest_stream(stream, options)).await.map(Response::new)
+    }
+
+    async fn write_batch(
+        &self,
+        request: Request<WriteBatchRequest>,
+    ) -> Result<Response<UploadResponse>, Status> {
+        let _timer = METRICS.call_duration.with_label_values(&["WriteBatch"]).start_timer();
+        let header_key = idempotency_key(request.metadata())?;
+        let options = IngestOptions::from_request(&request)?;
+        let batch = request.into_inner();
+
+        // The key can also travel in the request, for callers that cannot set metadata
+        let tenant = options.tenant.as_deref().or(Some(batch.tenant_name.as_str()).filter(|tenant| !tenant.is_empty()));
+        let idempotency_key = match (header_key, batch.idempotency_key.is_empty()) {
+            (Some(key), _) => Some(key),
+            (None, false) => Some(batch.idempotency_key.clone()),
+            (None, true) => None,
+        }
+        .map(|key| scoped_idempotency_key(tenant, key))
+        .transpose()?;
+
+        self.run_idempotent(idempotency_key, self.ingest_batch(batch, options)).await.map(Response::new)
+    }
+
+    type StreamLogsBidiStream = AckStream;
+
+    async fn stream_logs_bidi(
+        &self,
+        request: Request<Streaming<LogEntry>>,
+    ) -> Result<Response<Self::StreamLogsBidiStream>, Status> {
+        let mut options = IngestOptions::from_request(&request)?;
         let mut stream = request.into_inner();
-        let mut log_entries = vec![];
+        let (tx, rx) = mpsc::channel(16);
+        let service = self.clone();
 
-        // Process the incoming stream of log entries
-        while let Some(log_entry) = stream.next().await {
-            match log_entry {
-                Ok(entry) => {
-                    // Convert LogEntry to serde_json::Value for processing
-                    let log_value = json!({
-                        // "datetime": entry.datetime,
-                        "tenant_name": entry.tenant_name,
-                        "item_id": entry.item_id,
-                        "status": entry.status,
-                        "qty": entry.qty,
-                        "metadata": entry.metadata,
-                    });
-
-                    log_entries.push(log_value);
+        tokio::spawn(async move {
+            let _timer = METRICS.call_duration.with_label_values(&["StreamLogsBidi"]).start_timer();
+            let mut pending = vec![];
+            let mut buffered = BufferedRows::default();
+            let mut permit = None;
+            let mut first_row = 0u64;
+            let mut ticker = tokio::time::interval(service.ack_settings.interval);
+            // The first tick completes immediately
+            ticker.tick().await;
+
+            loop {
+                // Decide whether to commit the pending rows and whether the stream is over
+                let (flush, done) = tokio::select! {
+                    log_entry = stream.next() => match log_entry {
+                        Some(Ok(mut entry)) => {
+                            let admitted = options
+                                .resolve_entry(&mut entry)
+                                .and_then(|_| service.admit_entries(std::slice::from_ref(&entry), &options, &mut permit));
+                            if let Err(e) = admitted {
+                                let _ = tx.send(Err(e.into())).await;
+                                break;
+                            }
+                            pending.push(log_entry_to_value(entry));
+                            buffered.add(1);
+                            (pending.len() >= service.ack_settings.batch_size, false)
+                        }
+                        Some(Err(status)) => {
+                            error!("Error reading bidirectional stream after {} entries: {}", first_row as usize + pending.len(), status);
+                            if service.stream_error_policy == StreamErrorPolicy::Rollback {
+                                info!("Discarding {} unacknowledged entries.", pending.len());
+                                pending.clear();
+                                buffered.clear();
+                            }
+                            (true, true)
+                        }
+                        None => (true, true),
+                    },
+                    _ = ticker.tick() => (true, false),
+                    _ = service.drain.draining() => {
+                        info!("Server is draining, committing the {} pending entries.", pending.len());
+                        (true, true)
+                    }
+                };
+
+                if flush && !pending.is_empty() {
+                    let chunk = std::mem::take(&mut pending);
+                    let ack = service.commit_chunk(&chunk, &options, first_row).await;
+                    buffered.clear();
+                    first_row += chunk.len() as u64;
+
+                    let failed = ack.is_err();
+                    if tx.send(ack).await.is_err() {
+                        info!("Client stopped listening for acknowledgements.");
+                        break;
+                    }
+                    if failed {
+                        break;
+                    }
+                }
+
+                if done {
+                    break;
                 }
-                Err(_) => return Err(Status::internal("Error reading stream")),
             }
+        });
+
+        Ok(Response::new(AckStream { receiver: rx }))
+    }
+
+    async fn list_undeliverable_uploads(
+        &self,
+        request: Request<ListUndeliverableUploadsRequest>,
+    ) -> Result<Response<ListUndeliverableUploadsResponse>, Status> {
+        // Admins see the files of every tenant, optionally filtered, and tenants only their own.
+        // Unauthenticated callers see nothing.
+        let extensions = request.extensions();
+        let caller_tenant = match (extensions.get::<AdminCaller>(), extensions.get::<AuthorizedTenant>()) {
+            (Some(_), _) => None,
+            (None, Some(AuthorizedTenant(tenant))) => Some(tenant.clone()),
+            (None, None) => {
+                return Err(Status::unauthenticated("ListUndeliverableUploads needs an admin or a tenant token"));
+            }
+        };
+        let request = request.into_inner();
+        let tenant = match caller_tenant {
+            Some(tenant) if !request.tenant_name.is_empty() && request.tenant_name != tenant => {
+                return Err(ParquetbError::Unauthorized(format!(
+                    "Token is not authorized for tenant {}",
+                    request.tenant_name
+                )).into());
+            }
+            Some(tenant) => Some(tenant),
+            None => Some(request.tenant_name).filter(|tenant| !tenant.is_empty()),
+        };
+
+        let uploads = self.uploads.undeliverable(tenant.as_deref())
+            .into_iter()
+            .map(|upload| UndeliverableUpload {
+                file_name: upload.file_name,
+                tenant_name: upload.tenant,
+                object_key: upload.location.key,
+                bucket: upload.location.bucket.unwrap_or_default(),
+                destinations: upload.destinations.into_iter()
+                    .map(|state| DestinationStatus {
+                        name: state.name,
+                        delivered: state.delivered,
+                        attempts: state.attempts,
+                        last_error: state.last_error.unwrap_or_default(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Ok(Response::new(ListUndeliverableUploadsResponse { uploads }))
+    }
+
+    async fn get_committed_offset(
+        &self,
+        request: Request<CommittedOffsetRequest>,
+    ) -> Result<Response<CommittedOffsetResponse>, Status> {
+        let options = IngestOptions::from_request(&request)?;
+        let mut request = request.into_inner();
+        if request.producer_id.is_empty() {
+            return Err(ParquetbError::Validation("Missing producer ID".to_string()).into());
+        }
+        // The tenant defaults to the token's or the header's, and a token's cannot be overridden
+        match options.tenant {
+            Some(tenant) if request.tenant_name.is_empty() => request.tenant_name = tenant,
+            Some(tenant) if options.authorized && request.tenant_name != tenant => {
+                return Err(ParquetbError::Unauthorized(format!(
+                    "Token is not authorized for tenant {}",
+                    request.tenant_name
+                )).into());
+            }
+            _ => {}
+        }
+        if request.tenant_name.is_empty() {
+            return Err(ParquetbError::Validation("Missing tenant name".to_string()).into());
         }
 
+        let committed = self.offsets.committed(&request.tenant_name, &request.producer_id);
+        info!("Committed offset of producer {} for tenant {}: {:?}", request.producer_id, request.tenant_name, committed);
+
+        Ok(Response::new(CommittedOffsetResponse {
+            producer_id: request.producer_id,
+            sequence: committed.unwrap_or_default(),
+            found: committed.is_some(),
+        }))
+    }
+}
+
+// Build the response of a committed batch
+fn upload_response(processed: &ProcessedLogs, partial: bool) -> UploadResponse {
+    let message = if partial {
+        format!(
+            "Stream ended early: the {} rows received before it was cut off were processed, {} of them written to {}.",
+            processed.rows_received, processed.rows_written, processed.file_name
+        )
+    } else if processed.rows_written == 0 {
+        format!(
+            "No new entries to write: {} already committed, {} duplicates dropped.",
+            processed.rows_skipped, processed.duplicates_dropped
+        )
+    } else if processed.rows_rejected > 0 {
+        format!(
+            "Parquet file created and queued for upload! {} rows written, {} rejected by data quality rules.",
+            processed.rows_written, processed.rows_rejected
+        )
+    } else {
+        "Parquet file created and queued for upload!".to_string()
+    };
+
+    UploadResponse {
+        message,
+        rows_committed: processed.rows_written as u64,
+        partial,
+        rows_received: processed.rows_received as u64,
+        rows_skipped: processed.rows_skipped as u64,
+        duplicates_dropped: processed.duplicates_dropped as u64,
+        object_key: processed.location.key.clone(),
+        bucket: processed.location.bucket.clone().unwrap_or_default(),
+    }
+}
+
+// Keys only collide within one tenant, so the call's tenant must be known before it starts
+fn scoped_idempotency_key(tenant: Option<&str>, key: String) -> Result<String, ParquetbError> {
+    match tenant {
+        Some(tenant) => Ok(format!("{}/{}", tenant, key)),
+        None => Err(ParquetbError::Validation(
+            "An idempotency key needs the 'tenant' header or a batch tenant".to_string(),
+        )),
+    }
+}
+
+// Read the optional client-supplied batch ID from the request metadata
+fn idempotency_key(metadata: &MetadataMap) -> Result<Option<String>, ParquetbError> {
+    match metadata.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(key) if !key.is_empty() => Ok(Some(key.to_string())),
+            _ => Err(ParquetbError::Validation("Invalid idempotency key".to_string())),
+        },
+        None => Ok(None),
+    }
+}
+
+impl MyParquetbService {
+    async fn process_logs(
+        &self,
+        log_entries: &[serde_json::Value],
+        options: &IngestOptions,
+    ) -> Result<ProcessedLogs<'_>, ParquetbError> {
+        info!("Starting log processing.");
+        let _timer = METRICS.process_logs_duration.start_timer();
+
+        // Use the first log entry to get tenant info
+        let first_log = &log_entries[0];
+        let received_count = log_entries.len();
+        let tenant_name = match first_log["tenant_name"].as_str() {
+            Some(tenant) if !tenant.is_empty() => tenant,
+            _ => {
+                error!("Tenant name missing in the first log entry.");
+                return Err(ParquetbError::Validation("Missing tenant name".to_string()));
+            }
+        };
+        // The tenant name ends up in the file name, so it must not escape the working directory
+        if tenant_name.contains(['/', '\\']) || tenant_name.contains("..") {
+            error!("Invalid tenant name: {}", tenant_name);
+            return Err(ParquetbError::Validation(format!("Invalid tenant name: {}", tenant_name)));
+        }
+        info!("Tenant name: {}", tenant_name);
+
+        // Evaluate the tenant's data quality rules before anything is written
+        let log_entries = match self.rules.get(tenant_name) {
+            Some(tenant_rules) => apply_rules(tenant_name, log_entries, tenant_rules),
+            None => log_entries.to_vec(),
+        };
+        let rows_rejected = received_count - log_entries.len();
         if log_entries.is_empty() {
-            return Err(Status::invalid_argument("No log entries provided"));
+            error!("All {} log entries were rejected by data quality rules.", rows_rejected);
+            return Err(ParquetbError::Validation("All log entries were rejected by data quality rules".to_string()));
         }
+        info!("{} log entries accepted, {} rejected by data quality rules.", log_entries.len(), rows_rejected);
 
-        // Process the log entries and generate Parquet file
-        let file_name = match process_logs(&log_entries).await {
-            Ok(file_name) => file_name,
-            Err(e) => return Err(Status::internal(format!("Error processing logs: {}", e))),
+        // Drop the entries repeating a natural key, within the batch or the table's window
+        let table = options.table.as_deref().unwrap_or_default();
+        let (log_entries, duplicates_dropped, dedup) = match self.tenants.get(tenant_name).and_then(|tenant| tenant.dedup.as_ref()) {
+            Some(dedup_settings) => {
+                let unique = self.dedup.filter(tenant_name, table, dedup_settings, log_entries);
+                (unique.log_entries, unique.dropped, Some(unique.reservation))
+            }
+            None => (log_entries, 0, None),
         };
+        if log_entries.is_empty() {
+            info!("All {} accepted log entries were duplicates, nothing to write.", duplicates_dropped);
+            return Ok(ProcessedLogs {
+                tenant_name: tenant_name.to_string(),
+                rows_rejected,
+                duplicates_dropped,
+                ..Default::default()
+            });
+        }
+
+        // Generate the current UTC datetime instead of using the user-provided datetime
+        let datetime = chrono::Utc::now();
+        let formatted_datetime = datetime.format("%Y%m%d_%H%M").to_string();
+        info!("Generated datetime: {}", formatted_datetime);
 
-        // Call send_log to upload the Parquet file
-        if let Err(e) = send_log(&file_name, &log_entries[0]["tenant_name"].as_str().unwrap(), &file_name).await {
-            return Err(Status::internal(format!("Error sending parquetb file: {}", e)));
+        // Refuse to write once the tenant stored its daily budget
+        let limits = self.tenant_limits(tenant_name);
+        if let Some(limits) = limits {
+            self.quotas.check_storage(tenant_name, limits)?;
         }
 
-        // Return a successful response
-        let reply = UploadResponse {
-            message: "Parquet file created and uploaded successfully!".to_string(),
+        // Each table rotates its own files
+        let file_base = if table.is_empty() {
+            format!("{}_{}", tenant_name, formatted_datetime)
+        } else {
+            format!("{}_{}_{}", tenant_name, table, formatted_datetime)
         };
-        Ok(Response::new(reply))
-    }
-}
+        let file_name = match reserve_file_name(self.uploads.spool_dir(), &[self.uploads.output_dir()], &file_base, "parquet") {
+            Ok(file_name) => file_name,
+            Err(e) => {
+                error!("Failed to reserve a Parquet file name: {}", e);
+                return Err(ParquetbError::Storage(e.to_string()));
+            }
+        };
+        info!("Generated file name: {}", file_name);
+        let file_path = self.uploads.spool_dir().join(&file_name);
+        // Removes the placeholder, or the partly written file, on every early return
+        let spool_file = ReservedFile::new(file_path.clone());
 
-async fn process_logs(log_entries: &[serde_json::Value]) -> Result<String, Box<dyn Error>> {
-    info!("Starting log processing.");
+        // The routing table decides the bucket and key the file is stored under
+        let location = self.routes.resolve(&KeyContext { tenant: tenant_name, table, datetime }, &file_name);
+        info!("Resolved object key: {}", location.key);
 
-    // Use the first log entry to build the schema and get tenant info
-    let first_log = &log_entries[0];
-    let tenant_name = match first_log["tenant_name"].as_str() {
-        Some(tenant) => tenant,
-        None => {
-            error!("Tenant name missing in the first log entry.");
-            return Err("Missing tenant name".into());
+        // Build the schema from the metadata fields of every accepted entry
+        let mut batch_schema = build_schema(&log_entries[0]);
+        for log_entry in &log_entries[1..] {
+            batch_schema = match merge_schemas(&batch_schema, &build_schema(log_entry)) {
+                Ok(schema) => schema,
+                Err(e) => {
+                    error!("Log entries of one batch disagree on the schema: {}", e);
+                    return Err(ParquetbError::SchemaConflict(e));
+                }
+            };
         }
-    };
-    info!("Tenant name: {}", tenant_name);
 
-    // Generate the current UTC datetime instead of using the user-provided datetime
-    let datetime = chrono::Utc::now();
-    let formatted_datetime = datetime.format("%Y%m%d_%H%M").to_string();
-    info!("Generated datetime: {}", formatted_datetime);
+        // Evolve the table's schema with the new fields of this batch, committed once the file is written
+        let schema = match self.schemas.evolve(tenant_name, table, &batch_schema) {
+            Ok(schema) => schema,
+            Err(e) => {
+                error!("Schema conflict: {}", e);
+                return Err(ParquetbError::SchemaConflict(e));
+            }
+        };
+        info!("Schema built successfully.");
 
-    let file_name = format!("{}_{}.parquet", tenant_name, formatted_datetime);
-    info!("Generated file name: {}", file_name);
+        // Convert each log entry to Arrow arrays, collecting them column by column
+        let mut columns: Vec<Vec<ArrayRef>> = vec![Vec::new(); schema.fields().len()];
+        for log_entry in &log_entries {
+            match log_entry_to_arrays(log_entry, &schema) {
+                Ok(array) => {
+                    info!("Converted log entry to arrays.");
+                    for (column, value) in columns.iter_mut().zip(array) {
+                        column.push(value);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to convert log entry to arrays: {}", e);
+                    return Err(ParquetbError::SchemaConflict(e.to_string()));
+                }
+            }
+        }
 
-    // Build the schema based on the first log entry
-    let schema = build_schema(first_log);
-    info!("Schema built successfully.");
+        // Concatenate the single-row arrays into one array per column
+        let mut arrays = Vec::with_capacity(columns.len());
+        for column in &columns {
+            let parts: Vec<&dyn Array> = column.iter().map(|array| array.as_ref()).collect();
+            match concat(&parts) {
+                Ok(array) => arrays.push(array),
+                Err(e) => {
+                    error!("Failed to concatenate column arrays: {}", e);
+                    return Err(ParquetbError::SchemaConflict(e.to_string()));
+                }
+            }
+        }
 
-    // Convert each log entry to Arrow arrays
-    let mut arrays = vec![];
-    for log_entry in log_entries {
-        match log_entry_to_arrays(log_entry, &schema) {
-            Ok(array) => {
-                info!("Converted log entry to arrays.");
-                arrays.extend(array);
+        // Write to Parquet file
+        let timer = METRICS.write_parquet_duration.start_timer();
+        let schema = Arc::new(schema);
+        let written = write_parquet_file(&file_path.to_string_lossy(), schema.clone(), arrays, options.writer_properties(spool_metadata(tenant_name, &location)));
+        timer.observe_duration();
+        match written {
+            Ok(_) => {
+                info!("Parquet file written successfully.");
+                if let Err(e) = self.schemas.commit(tenant_name, table, &schema) {
+                    error!("Schema conflict with a concurrent batch: {}", e);
+                    return Err(ParquetbError::SchemaConflict(e));
+                }
+                let file_size = std::fs::metadata(&file_path).map(|metadata| metadata.len()).unwrap_or_default();
+                METRICS.file_size.observe(file_size as f64);
+                METRICS.rows_per_file.observe(log_entries.len() as f64);
+                if limits.is_some() {
+                    self.quotas.record_stored(tenant_name, file_size);
+                }
             }
             Err(e) => {
-                error!("Failed to convert log entry to arrays: {}", e);
-                return Err(e.into());
+                error!("Failed to write Parquet file: {}", e);
+                return Err(ParquetbError::Storage(e.to_string()));
             }
         }
-    }
 
-    // Write to Parquet file
-    match write_parquet_file(&file_name, Arc::new(schema), arrays) {
-        Ok(_) => info!("Parquet file written successfully."),
-        Err(e) => {
-            error!("Failed to write Parquet file: {}", e);
-            return Err(e.into());
-        }
-    }
+        info!("Log processing completed successfully.");
 
-    info!("Log processing completed successfully.");
-    Ok(file_name)
+        Ok(ProcessedLogs {
+            tenant_name: tenant_name.to_string(),
+            file_name,
+            location,
+            rows_written: log_entries.len(),
+            rows_rejected,
+            duplicates_dropped,
+            dedup,
+            spool_file: Some(spool_file),
+            ..Default::default()
+        })
+    }
 }
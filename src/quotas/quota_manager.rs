@@ -0,0 +1,296 @@
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDate, Utc};
+use serde_json::Value;
+use tracing::info;
+
+use crate::parquetb_error::ParquetbError;
+
+// Amount per second a token bucket refills at; always finite and positive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate(f64);
+
+impl Rate {
+    pub fn new(per_sec: f64) -> Option<Self> {
+        (per_sec.is_finite() && per_sec > 0.0).then_some(Rate(per_sec))
+    }
+
+    fn from_value(value: &Value, key: &str) -> Result<Option<Self>, Box<dyn Error>> {
+        match value.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(rate) => match rate.as_f64().and_then(Rate::new) {
+                Some(rate) => Ok(Some(rate)),
+                None => Err(format!("'{}' must be a positive number", key).into()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Ingestion limits of a tenant, each one optional
+#[derive(Debug, Clone, Default)]
+pub struct TenantLimits {
+    pub rows_per_sec: Option<Rate>,
+    pub bytes_per_sec: Option<Rate>,
+    pub max_concurrent_streams: Option<usize>,
+    pub daily_bytes: Option<u64>,
+}
+
+impl TenantLimits {
+    pub fn from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        if !value.is_object() {
+            return Err("'limits' must be an object".into());
+        }
+
+        Ok(TenantLimits {
+            rows_per_sec: Rate::from_value(value, "rows_per_sec")?,
+            bytes_per_sec: Rate::from_value(value, "bytes_per_sec")?,
+            max_concurrent_streams: positive_integer(value, "max_concurrent_streams")?.map(|max| max as usize),
+            daily_bytes: positive_integer(value, "daily_bytes")?,
+        })
+    }
+}
+
+fn positive_integer(value: &Value, key: &str) -> Result<Option<u64>, Box<dyn Error>> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(limit) => match limit.as_u64() {
+            Some(limit) if limit > 0 => Ok(Some(limit)),
+            _ => Err(format!("'{}' must be a positive integer", key).into()),
+        },
+    }
+}
+
+// Longest wait reported to a throttled tenant
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Rate limiter refilled continuously at `rate` per second, holding at most one second of budget.
+// Requests are admitted while the budget is positive and charged afterwards, so a batch bigger
+// than one second of budget goes through and the next requests wait for the debt to be repaid.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new() -> Self {
+        TokenBucket { tokens: f64::MAX, refilled_at: Instant::now() }
+    }
+
+    // Time to wait before the next request is admitted, or None when it is admitted and charged
+    fn try_take(&mut self, rate: Rate, amount: f64) -> Option<Duration> {
+        let Rate(rate) = rate;
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.refilled_at = now;
+
+        if self.tokens <= 0.0 {
+            // Tiny rates can owe more than a client should be told to wait
+            let wait = Duration::try_from_secs_f64(-self.tokens / rate).unwrap_or(MAX_RETRY_AFTER);
+            return Some(wait.clamp(Duration::from_millis(1), MAX_RETRY_AFTER));
+        }
+        self.tokens -= amount;
+        None
+    }
+}
+
+#[derive(Debug)]
+struct TenantUsage {
+    rows: TokenBucket,
+    bytes: TokenBucket,
+    streams: usize,
+    day: NaiveDate,
+    stored_bytes: u64,
+}
+
+impl TenantUsage {
+    fn new() -> Self {
+        TenantUsage {
+            rows: TokenBucket::new(),
+            bytes: TokenBucket::new(),
+            streams: 0,
+            day: Utc::now().date_naive(),
+            stored_bytes: 0,
+        }
+    }
+
+    // The stored bytes count starts over every UTC day
+    fn roll_day(&mut self) {
+        let today = Utc::now().date_naive();
+        if self.day != today {
+            self.day = today;
+            self.stored_bytes = 0;
+        }
+    }
+}
+
+// Tracks what each tenant uses against its limits
+#[derive(Debug, Default)]
+pub struct QuotaManager {
+    usage: Mutex<HashMap<String, TenantUsage>>,
+}
+
+// One of the tenant's concurrent streams, released when dropped
+pub struct StreamPermit {
+    quotas: Arc<QuotaManager>,
+    tenant: String,
+}
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        if let Some(usage) = self.quotas.usage.lock().unwrap().get_mut(&self.tenant) {
+            usage.streams = usage.streams.saturating_sub(1);
+        }
+    }
+}
+
+fn until_next_day() -> Duration {
+    let now = Utc::now();
+    let tomorrow = (now.date_naive() + chrono::Days::new(1)).and_hms_opt(0, 0, 0).unwrap_or_default();
+    (tomorrow - now.naive_utc()).to_std().unwrap_or_default()
+}
+
+impl QuotaManager {
+    pub fn acquire_stream(self: &Arc<Self>, tenant: &str, limits: &TenantLimits) -> Result<StreamPermit, ParquetbError> {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(tenant.to_string()).or_insert_with(TenantUsage::new);
+
+        if let Some(max) = limits.max_concurrent_streams {
+            if usage.streams >= max {
+                info!("Tenant {} reached its limit of {} concurrent streams", tenant, max);
+                return Err(ParquetbError::Quota(
+                    format!("Tenant {} already has {} concurrent streams", tenant, max),
+                    Duration::from_secs(1),
+                ));
+            }
+        }
+        usage.streams += 1;
+
+        Ok(StreamPermit { quotas: self.clone(), tenant: tenant.to_string() })
+    }
+
+    // Charge rows and bytes against the tenant's rates
+    pub fn admit(&self, tenant: &str, limits: &TenantLimits, rows: usize, bytes: usize) -> Result<(), ParquetbError> {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(tenant.to_string()).or_insert_with(TenantUsage::new);
+
+        if let Some(rate) = limits.rows_per_sec {
+            if let Some(retry_after) = usage.rows.try_take(rate, rows as f64) {
+                return Err(ParquetbError::Quota(
+                    format!("Tenant {} is over its limit of {} rows per second", tenant, rate),
+                    retry_after,
+                ));
+            }
+        }
+        if let Some(rate) = limits.bytes_per_sec {
+            if let Some(retry_after) = usage.bytes.try_take(rate, bytes as f64) {
+                return Err(ParquetbError::Quota(
+                    format!("Tenant {} is over its limit of {} bytes per second", tenant, rate),
+                    retry_after,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Fail when the tenant already stored its daily budget
+    pub fn check_storage(&self, tenant: &str, limits: &TenantLimits) -> Result<(), ParquetbError> {
+        let Some(daily_bytes) = limits.daily_bytes else {
+            return Ok(());
+        };
+
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(tenant.to_string()).or_insert_with(TenantUsage::new);
+        usage.roll_day();
+
+        if usage.stored_bytes >= daily_bytes {
+            return Err(ParquetbError::Quota(
+                format!("Tenant {} stored its daily limit of {} bytes", tenant, daily_bytes),
+                until_next_day(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn record_stored(&self, tenant: &str, bytes: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(tenant.to_string()).or_insert_with(TenantUsage::new);
+        usage.roll_day();
+        usage.stored_bytes += bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rate(per_sec: f64) -> Rate {
+        Rate::new(per_sec).unwrap()
+    }
+
+    #[test]
+    fn new_bucket_admits_a_burst_then_waits_for_the_debt() {
+        let mut bucket = TokenBucket::new();
+        assert_eq!(bucket.try_take(rate(10.0), 30.0), None);
+
+        // Capped at one second of budget, then charged 30: 2 seconds in debt
+        let wait = bucket.try_take(rate(10.0), 1.0).unwrap();
+        assert!(wait > Duration::from_millis(1900) && wait <= Duration::from_secs(2), "{:?}", wait);
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut bucket = TokenBucket { tokens: -1.0, refilled_at: Instant::now() - Duration::from_millis(200) };
+        assert_eq!(bucket.try_take(rate(10.0), 1.0), None);
+        assert!(bucket.tokens < 1.0);
+    }
+
+    #[test]
+    fn tiny_rate_wait_is_clamped() {
+        let mut bucket = TokenBucket { tokens: -1e12, refilled_at: Instant::now() };
+        assert_eq!(bucket.try_take(rate(1e-300), 1.0), Some(MAX_RETRY_AFTER));
+    }
+
+    #[test]
+    fn limits_must_be_positive() {
+        let limits = TenantLimits::from_value(&json!({ "rows_per_sec": 2.5, "max_concurrent_streams": 2, "daily_bytes": 100 })).unwrap();
+        assert_eq!(limits.rows_per_sec, Some(rate(2.5)));
+        assert_eq!(limits.max_concurrent_streams, Some(2));
+        assert_eq!(limits.daily_bytes, Some(100));
+
+        for invalid in [
+            json!({ "rows_per_sec": 0 }),
+            json!({ "bytes_per_sec": "fast" }),
+            json!({ "max_concurrent_streams": 0 }),
+            json!({ "max_concurrent_streams": 1.5 }),
+            json!({ "daily_bytes": "10GB" }),
+            json!({ "daily_bytes": -1 }),
+        ] {
+            assert!(TenantLimits::from_value(&invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn stream_permits_are_released_on_drop() {
+        let quotas = Arc::new(QuotaManager::default());
+        let limits = TenantLimits { max_concurrent_streams: Some(1), ..Default::default() };
+
+        let permit = quotas.acquire_stream("acme", &limits).unwrap();
+        assert!(matches!(quotas.acquire_stream("acme", &limits), Err(ParquetbError::Quota(..))));
+        drop(permit);
+        assert!(quotas.acquire_stream("acme", &limits).is_ok());
+    }
+}
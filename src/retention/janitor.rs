@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tracing::{error, info, warn};
+
+use crate::retention::retention_policy::RetentionPolicy;
+
+// What the janitor removes from the output directory, and how often it looks
+#[derive(Debug, Clone)]
+pub struct JanitorSettings {
+    pub output_dir: PathBuf,
+    pub retention: RetentionPolicy,
+    // Delivered files are deleted oldest first while they take more than this
+    pub max_bytes: Option<u64>,
+    pub interval: Duration,
+}
+
+impl JanitorSettings {
+    // Whether the janitor has anything to delete at all
+    pub fn deletes_files(&self) -> bool {
+        matches!(self.retention, RetentionPolicy::KeepFor(_)) || self.max_bytes.is_some()
+    }
+}
+
+// Periodically delete the delivered files past their retention or over the disk budget.
+// Spooled files are never touched, they are not delivered yet.
+pub fn spawn_janitor(settings: JanitorSettings) {
+    if !settings.deletes_files() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(settings.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = sweep(&settings) {
+                error!("Janitor failed to clean {:?}: {}", settings.output_dir, e);
+            }
+        }
+    });
+}
+
+struct DeliveredFile {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+fn sweep(settings: &JanitorSettings) -> Result<(), Box<dyn Error>> {
+    let mut files = delivered_files(&settings.output_dir)?;
+    // Oldest first
+    files.sort_by_key(|file| file.modified);
+
+    let now = SystemTime::now();
+    let mut total: u64 = files.iter().map(|file| file.size).sum();
+    let mut deleted = 0;
+
+    for file in files {
+        let expired = match settings.retention {
+            RetentionPolicy::KeepFor(keep) => now.duration_since(file.modified).unwrap_or_default() > keep,
+            _ => false,
+        };
+        let over_budget = settings.max_bytes.is_some_and(|max_bytes| total > max_bytes);
+        if !expired && !over_budget {
+            continue;
+        }
+
+        // One file that cannot be deleted must not keep the others around
+        if let Err(e) = std::fs::remove_file(&file.path) {
+            warn!("Janitor failed to delete {:?}: {}", file.path, e);
+            continue;
+        }
+        total -= file.size;
+        deleted += 1;
+    }
+
+    if deleted > 0 {
+        info!("Janitor deleted {} files from {:?}, {} bytes left", deleted, settings.output_dir, total);
+    }
+    Ok(())
+}
+
+// Parquet files of the output directory
+fn delivered_files(output_dir: &Path) -> Result<Vec<DeliveredFile>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(output_dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().is_none_or(|extension| extension != "parquet") {
+            continue;
+        }
+        let metadata = std::fs::metadata(&path)?;
+        files.push(DeliveredFile { modified: metadata.modified()?, size: metadata.len(), path });
+    }
+    Ok(files)
+}
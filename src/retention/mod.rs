@@ -0,0 +1,2 @@
+pub mod retention_policy;
+pub mod janitor;
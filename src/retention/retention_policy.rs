@@ -0,0 +1,30 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+// How long delivered files are kept in the output directory
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    // Delete the local copy as soon as the upload is verified
+    DeleteAfterUpload,
+    // Keep the files as a local cache for this long after they were written
+    KeepFor(Duration),
+    #[default]
+    KeepAll,
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = String;
+
+    // "delete", "keep", or a number of hours such as "24h"
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "delete" => Ok(RetentionPolicy::DeleteAfterUpload),
+            "keep" => Ok(RetentionPolicy::KeepAll),
+            other => other
+                .strip_suffix('h')
+                .and_then(|hours| hours.parse::<u64>().ok())
+                .map(|hours| RetentionPolicy::KeepFor(Duration::from_secs(hours * 60 * 60)))
+                .ok_or_else(|| format!("Unknown retention policy: {}", other)),
+        }
+    }
+}
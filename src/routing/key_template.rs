@@ -0,0 +1,141 @@
+use std::error::Error;
+
+use chrono::{DateTime, Utc};
+
+// Values substituted in a key template
+pub struct KeyContext<'a> {
+    pub tenant: &'a str,
+    pub table: &'a str,
+    pub datetime: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Tenant,
+    Table,
+    Year,
+    Month,
+    Day,
+    Hour,
+}
+
+// Object key prefix such as "{tenant}/{table}/{yyyy}/{mm}/{dd}/". Placeholders are {tenant},
+// {table}, {yyyy}, {mm}, {dd} and {hh}; dates are those of the file, in UTC.
+#[derive(Debug, Clone)]
+pub struct KeyTemplate {
+    parts: Vec<Part>,
+}
+
+// Keeps the <tenant>/<table>/ layout of the files written before routing
+impl Default for KeyTemplate {
+    fn default() -> Self {
+        KeyTemplate {
+            parts: vec![Part::Tenant, Part::Literal("/".to_string()), Part::Table, Part::Literal("/".to_string())],
+        }
+    }
+}
+
+impl KeyTemplate {
+    pub fn parse(template: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| format!("Unclosed placeholder in {}", template))? + start;
+            parts.push(match &rest[start + 1..end] {
+                "tenant" => Part::Tenant,
+                "table" => Part::Table,
+                "yyyy" => Part::Year,
+                "mm" => Part::Month,
+                "dd" => Part::Day,
+                "hh" => Part::Hour,
+                other => return Err(format!("Unknown placeholder {{{}}} in {}", other, template).into()),
+            });
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("Unopened placeholder in {}", template).into());
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(KeyTemplate { parts })
+    }
+
+    // Key of a file under the rendered prefix. Empty segments, such as {table} for entries
+    // without a table, are left out.
+    pub fn key(&self, context: &KeyContext, file_name: &str) -> String {
+        let prefix: String = self.parts.iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                Part::Tenant => context.tenant.to_string(),
+                Part::Table => context.table.to_string(),
+                Part::Year => context.datetime.format("%Y").to_string(),
+                Part::Month => context.datetime.format("%m").to_string(),
+                Part::Day => context.datetime.format("%d").to_string(),
+                Part::Hour => context.datetime.format("%H").to_string(),
+            })
+            .collect();
+
+        prefix.split('/')
+            .filter(|segment| !segment.is_empty())
+            .chain(std::iter::once(file_name))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context<'a>(tenant: &'a str, table: &'a str) -> KeyContext<'a> {
+        KeyContext { tenant, table, datetime: Utc.with_ymd_and_hms(2026, 3, 7, 9, 30, 0).unwrap() }
+    }
+
+    #[test]
+    fn rejects_unclosed_placeholder() {
+        let error = KeyTemplate::parse("{tenant}/{yyyy/").unwrap_err();
+        assert!(error.to_string().contains("Unclosed placeholder"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_unknown_placeholder() {
+        let error = KeyTemplate::parse("{tenant}/{month}/").unwrap_err();
+        assert!(error.to_string().contains("Unknown placeholder {month}"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_unopened_placeholder() {
+        assert!(KeyTemplate::parse("{tenant}/yyyy}/").is_err());
+    }
+
+    #[test]
+    fn renders_every_placeholder() {
+        let template = KeyTemplate::parse("logs/{tenant}/{table}/{yyyy}/{mm}/{dd}/{hh}/").unwrap();
+        assert_eq!(
+            template.key(&context("acme", "orders"), "file.parquet"),
+            "logs/acme/orders/2026/03/07/09/file.parquet"
+        );
+    }
+
+    #[test]
+    fn drops_empty_segments() {
+        let template = KeyTemplate::parse("{tenant}/{table}/{yyyy}/").unwrap();
+        assert_eq!(template.key(&context("acme", ""), "file.parquet"), "acme/2026/file.parquet");
+
+        let template = KeyTemplate::parse("//{tenant}//").unwrap();
+        assert_eq!(template.key(&context("acme", "orders"), "file.parquet"), "acme/file.parquet");
+    }
+
+    #[test]
+    fn default_keeps_the_tenant_and_table_layout() {
+        let template = KeyTemplate::default();
+        assert_eq!(template.key(&context("acme", "orders"), "file.parquet"), "acme/orders/file.parquet");
+        assert_eq!(template.key(&context("acme", ""), "file.parquet"), "acme/file.parquet");
+    }
+}
@@ -0,0 +1,3 @@
+pub mod key_template;
+pub mod object_location;
+pub mod routing_table;
@@ -0,0 +1,32 @@
+// Where a file is stored: the object key, and the bucket when the route names one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectLocation {
+    pub bucket: Option<String>,
+    pub key: String,
+    // <table>/<file> relative to the tenant, the file name minioc got before routing
+    pub object_name: String,
+}
+
+impl ObjectLocation {
+    // Location of the files written before routing, stored under <tenant>/<object name>
+    pub fn unrouted(tenant: &str, object_name: &str) -> Self {
+        ObjectLocation {
+            bucket: None,
+            key: format!("{}/{}", tenant, object_name),
+            object_name: object_name.to_string(),
+        }
+    }
+
+    // Location recorded without its object name, which is then the key relative to the tenant
+    pub fn without_object_name(tenant: &str, bucket: Option<String>, key: &str) -> Self {
+        let object_name = key
+            .strip_prefix(tenant)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .unwrap_or_else(|| key.rsplit('/').next().unwrap_or_default());
+        ObjectLocation {
+            bucket,
+            key: key.to_string(),
+            object_name: object_name.to_string(),
+        }
+    }
+}
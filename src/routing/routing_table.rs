@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use serde_json::Value;
+use tracing::info;
+
+use crate::routing::key_template::{KeyContext, KeyTemplate};
+use crate::routing::object_location::ObjectLocation;
+
+// Routes every tenant without its own route
+const DEFAULT_ROUTE: &str = "*";
+
+// Bucket and key prefix of the files of a tenant, or of one of its tables
+#[derive(Debug, Clone, Default)]
+pub struct Route {
+    pub bucket: Option<String>,
+    pub prefix: KeyTemplate,
+}
+
+impl Route {
+    // Fields missing from the value are inherited from the parent route
+    fn from_value(value: &Value, parent: Option<&Route>) -> Result<Self, Box<dyn Error>> {
+        let bucket = match value.get("bucket") {
+            Some(bucket) => Some(bucket.as_str().ok_or("'bucket' must be a string")?.to_string()),
+            None => parent.and_then(|parent| parent.bucket.clone()),
+        };
+        let prefix = match value.get("prefix") {
+            Some(prefix) => KeyTemplate::parse(prefix.as_str().ok_or("'prefix' must be a string")?)?,
+            None => parent.map(|parent| parent.prefix.clone()).unwrap_or_default(),
+        };
+        Ok(Route { bucket, prefix })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TenantRoutes {
+    route: Route,
+    tables: HashMap<String, Route>,
+}
+
+// Where the files of each tenant and table are stored. A table's route wins over its tenant's,
+// which wins over the "*" route; files matching none keep the <tenant>/<table>/ layout.
+#[derive(Debug, Clone, Default)]
+pub struct RoutingTable {
+    tenants: HashMap<String, TenantRoutes>,
+}
+
+impl RoutingTable {
+    pub fn from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let tenants = value.as_object().ok_or("Routing table must be a JSON object keyed by tenant")?;
+
+        let mut routes = HashMap::new();
+        for (tenant, tenant_value) in tenants {
+            let parse = || -> Result<TenantRoutes, Box<dyn Error>> {
+                let route = Route::from_value(tenant_value, None)?;
+                let mut tables = HashMap::new();
+                if let Some(table_values) = tenant_value.get("tables") {
+                    let table_values = table_values.as_object().ok_or("'tables' must be an object keyed by table")?;
+                    for (table, table_value) in table_values {
+                        let table_route = Route::from_value(table_value, Some(&route))
+                            .map_err(|e| format!("table {}: {}", table, e))?;
+                        tables.insert(table.clone(), table_route);
+                    }
+                }
+                Ok(TenantRoutes { route, tables })
+            };
+            let parsed = parse().map_err(|e| format!("Invalid route for tenant {}: {}", tenant, e))?;
+            routes.insert(tenant.clone(), parsed);
+        }
+
+        Ok(RoutingTable { tenants: routes })
+    }
+
+    fn route(&self, tenant: &str, table: &str) -> Option<&Route> {
+        let routes = self.tenants.get(tenant).or_else(|| self.tenants.get(DEFAULT_ROUTE))?;
+        Some(routes.tables.get(table).unwrap_or(&routes.route))
+    }
+
+    // Resolve the location of a file written at the given time
+    pub fn resolve(&self, context: &KeyContext, file_name: &str) -> ObjectLocation {
+        let default_route = Route::default();
+        let route = self.route(context.tenant, context.table).unwrap_or(&default_route);
+        ObjectLocation {
+            bucket: route.bucket.clone(),
+            key: route.prefix.key(context, file_name),
+            object_name: if context.table.is_empty() {
+                file_name.to_string()
+            } else {
+                format!("{}/{}", context.table, file_name)
+            },
+        }
+    }
+}
+
+// Load the routing table from a JSON file shaped as {"tenant": {"bucket", "prefix", "tables"}}
+pub fn load_routing_table(path: &Path) -> Result<RoutingTable, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let value: Value = serde_json::from_str(&content)?;
+
+    let routes = RoutingTable::from_value(&value)?;
+    info!("Loaded routes for {} tenants from {:?}", routes.tenants.len(), path);
+
+    Ok(routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    fn resolve(routes: &RoutingTable, tenant: &str, table: &str) -> ObjectLocation {
+        let datetime = Utc.with_ymd_and_hms(2026, 3, 7, 9, 30, 0).unwrap();
+        routes.resolve(&KeyContext { tenant, table, datetime }, "file.parquet")
+    }
+
+    fn routes() -> RoutingTable {
+        RoutingTable::from_value(&json!({
+            "*": { "prefix": "all/{tenant}/{yyyy}/" },
+            "acme": {
+                "bucket": "acme-logs",
+                "prefix": "logs/{table}/",
+                "tables": {
+                    "orders": { "prefix": "orders/{mm}/" },
+                    "audit": { "bucket": "acme-audit" }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn table_route_wins_over_tenant_route() {
+        let location = resolve(&routes(), "acme", "orders");
+        assert_eq!(location.bucket.as_deref(), Some("acme-logs"));
+        assert_eq!(location.key, "orders/03/file.parquet");
+    }
+
+    #[test]
+    fn table_route_inherits_missing_fields() {
+        let location = resolve(&routes(), "acme", "audit");
+        assert_eq!(location.bucket.as_deref(), Some("acme-audit"));
+        assert_eq!(location.key, "logs/audit/file.parquet");
+    }
+
+    #[test]
+    fn tenant_route_covers_other_tables() {
+        let location = resolve(&routes(), "acme", "events");
+        assert_eq!(location.bucket.as_deref(), Some("acme-logs"));
+        assert_eq!(location.key, "logs/events/file.parquet");
+    }
+
+    #[test]
+    fn default_route_covers_other_tenants() {
+        let location = resolve(&routes(), "globex", "orders");
+        assert_eq!(location.bucket, None);
+        assert_eq!(location.key, "all/globex/2026/file.parquet");
+        assert_eq!(location.object_name, "orders/file.parquet");
+    }
+
+    #[test]
+    fn without_routes_keeps_the_tenant_and_table_layout() {
+        let location = resolve(&RoutingTable::default(), "globex", "orders");
+        assert_eq!(location, ObjectLocation::unrouted("globex", "orders/file.parquet"));
+    }
+
+    #[test]
+    fn rejects_invalid_routes() {
+        assert!(RoutingTable::from_value(&json!({ "acme": { "prefix": "{nope}/" } })).is_err());
+        assert!(RoutingTable::from_value(&json!({ "acme": { "tables": { "orders": { "bucket": 1 } } } })).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::metrics::registry::METRICS;
+use crate::rules::quality_rule::{QualityRule, RuleAction};
+
+// Evaluate the tenant's rules on every log entry and drop the rows a rejecting rule fails on
+pub fn apply_rules(
+    tenant: &str,
+    log_entries: &[Value],
+    rules: &[QualityRule],
+) -> Vec<Value> {
+    let mut accepted = Vec::with_capacity(log_entries.len());
+
+    for log_entry in log_entries {
+        let mut rejected = false;
+
+        for rule in rules {
+            if rule.is_satisfied_by(log_entry) {
+                continue;
+            }
+            METRICS.rule_failures.with_label_values(&[tenant, &rule.name]).inc();
+
+            match rule.action {
+                RuleAction::Pass => {}
+                RuleAction::Warn => {
+                    warn!("Rule {} failed for tenant {} on entry {}", rule.name, tenant, log_entry);
+                }
+                RuleAction::Reject => {
+                    info!("Rule {} rejected an entry for tenant {}: {}", rule.name, tenant, log_entry);
+                    METRICS.rule_rejections.with_label_values(&[tenant, &rule.name]).inc();
+                    rejected = true;
+                }
+            }
+        }
+
+        if !rejected {
+            accepted.push(log_entry.clone());
+        }
+    }
+
+    accepted
+}
@@ -0,0 +1,34 @@
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use serde_json::Value;
+use tracing::info;
+
+use crate::rules::quality_rule::QualityRule;
+
+// Load the per-tenant data quality rules from a JSON file shaped as {"tenant": [rule, ...]}
+pub fn load_rules(path: &Path) -> Result<HashMap<String, Vec<QualityRule>>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let value: Value = serde_json::from_str(&content)?;
+
+    let tenants = value.as_object().ok_or("Rules file must contain a JSON object keyed by tenant")?;
+
+    let mut rules = HashMap::new();
+    for (tenant, tenant_rules) in tenants {
+        let definitions = tenant_rules
+            .as_array()
+            .ok_or_else(|| format!("Rules for tenant {} must be a list", tenant))?;
+
+        let parsed = definitions
+            .iter()
+            .map(QualityRule::from_value)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        info!("Loaded {} data quality rules for tenant {}", parsed.len(), tenant);
+        rules.insert(tenant.clone(), parsed);
+    }
+
+    Ok(rules)
+}
@@ -0,0 +1,3 @@
+pub mod quality_rule;
+pub mod load_rules;
+pub mod apply_rules;
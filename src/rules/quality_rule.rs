@@ -0,0 +1,100 @@
+
+use regex::Regex;
+use serde_json::Value;
+use std::error::Error;
+
+// What happens to a row that violates a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleAction {
+    Pass,
+    Warn,
+    Reject,
+}
+
+#[derive(Debug)]
+pub enum RuleCheck {
+    AllowedStatus(Vec<String>),
+    QtyRange { min: Option<f64>, max: Option<f64> },
+    QtyNonNegative,
+    RequiredMetadata(Vec<String>),
+    ItemIdPattern(Regex),
+}
+
+#[derive(Debug)]
+pub struct QualityRule {
+    pub name: String,
+    pub check: RuleCheck,
+    pub action: RuleAction,
+}
+
+impl QualityRule {
+    // Build a rule from its JSON definition, e.g. {"type": "qty_range", "min": 0, "action": "reject"}
+    pub fn from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let rule_type = value["type"].as_str().ok_or("Rule is missing 'type'")?;
+
+        let check = match rule_type {
+            "allowed_status" => RuleCheck::AllowedStatus(string_list(&value["values"])?),
+            "qty_range" => {
+                let min = optional_number(value, "min")?;
+                let max = optional_number(value, "max")?;
+                if min.is_none() && max.is_none() {
+                    return Err("'qty_range' rule needs a 'min' or a 'max'".into());
+                }
+                RuleCheck::QtyRange { min, max }
+            }
+            "qty_non_negative" => RuleCheck::QtyNonNegative,
+            "required_metadata" => RuleCheck::RequiredMetadata(string_list(&value["keys"])?),
+            "item_id_pattern" => {
+                let pattern = value["pattern"].as_str().ok_or("'item_id_pattern' rule is missing 'pattern'")?;
+                RuleCheck::ItemIdPattern(Regex::new(pattern)?)
+            }
+            other => return Err(format!("Unknown rule type: {}", other).into()),
+        };
+
+        let action = match value["action"].as_str().unwrap_or("reject") {
+            "pass" => RuleAction::Pass,
+            "warn" => RuleAction::Warn,
+            "reject" => RuleAction::Reject,
+            other => return Err(format!("Unknown rule action: {}", other).into()),
+        };
+
+        let name = value["name"].as_str().unwrap_or(rule_type).to_string();
+
+        Ok(QualityRule { name, check, action })
+    }
+
+    // Returns true when the log entry satisfies the rule. Qty rules fail on entries without a qty.
+    pub fn is_satisfied_by(&self, log_entry: &Value) -> bool {
+        match &self.check {
+            RuleCheck::AllowedStatus(allowed) => {
+                let status = log_entry["status"].as_str().unwrap_or_default();
+                allowed.iter().any(|value| value == status)
+            }
+            RuleCheck::QtyRange { min, max } => log_entry["qty"].as_f64().is_some_and(|qty| {
+                min.is_none_or(|min| qty >= min) && max.is_none_or(|max| qty <= max)
+            }),
+            RuleCheck::QtyNonNegative => log_entry["qty"].as_f64().is_some_and(|qty| qty >= 0.0),
+            RuleCheck::RequiredMetadata(keys) => {
+                keys.iter().all(|key| log_entry["metadata"].get(key).is_some())
+            }
+            RuleCheck::ItemIdPattern(pattern) => {
+                pattern.is_match(log_entry["item_id"].as_str().unwrap_or_default())
+            }
+        }
+    }
+}
+
+fn optional_number(value: &Value, key: &str) -> Result<Option<f64>, Box<dyn Error>> {
+    match value.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(number) => number.as_f64().map(Some).ok_or_else(|| format!("'{}' must be a number", key).into()),
+    }
+}
+
+fn string_list(value: &Value) -> Result<Vec<String>, Box<dyn Error>> {
+    let values = value.as_array().ok_or("Expected a list of strings")?;
+    values
+        .iter()
+        .map(|v| v.as_str().map(str::to_string).ok_or_else(|| "Expected a list of strings".into()))
+        .collect()
+}
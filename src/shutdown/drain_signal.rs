@@ -0,0 +1,30 @@
+use tokio::sync::watch;
+
+// Raised once when the server starts draining before shutdown
+#[derive(Debug, Clone)]
+pub struct DrainSignal {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Default for DrainSignal {
+    // A signal that is never raised
+    fn default() -> Self {
+        let (_sender, receiver) = watch::channel(false);
+        DrainSignal { receiver }
+    }
+}
+
+impl DrainSignal {
+    pub fn channel() -> (watch::Sender<bool>, DrainSignal) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, DrainSignal { receiver })
+    }
+
+    // Resolve once the server is draining; never resolves when the sender is gone
+    pub async fn draining(&self) {
+        let mut receiver = self.receiver.clone();
+        if receiver.wait_for(|draining| *draining).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
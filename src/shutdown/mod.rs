@@ -0,0 +1,2 @@
+pub mod drain_signal;
+pub mod wait_for_signal;
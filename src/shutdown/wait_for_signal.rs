@@ -0,0 +1,14 @@
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::info;
+
+// Resolve on the first SIGTERM or SIGINT
+pub async fn wait_for_signal() -> std::io::Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = sigterm.recv() => info!("Received SIGTERM"),
+        _ = sigint.recv() => info!("Received SIGINT"),
+    }
+    Ok(())
+}
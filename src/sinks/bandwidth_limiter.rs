@@ -0,0 +1,77 @@
+use std::num::NonZeroU64;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+// Bytes read and charged at a time when copying under a limit
+const THROTTLED_CHUNK_SIZE: usize = 256 * 1024;
+
+#[derive(Debug)]
+struct Budget {
+    bytes: f64,
+    refilled_at: Instant,
+}
+
+// Caps the combined throughput of every upload, whatever its sink. The budget refills at `bytes_per_sec` and holds
+// at most one second of it; a chunk bigger than what is left puts the budget in debt, and the
+// next chunk waits until it is paid back. Waiters are served in order.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_sec: f64,
+    budget: Mutex<Budget>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: NonZeroU64) -> Self {
+        let bytes_per_sec = bytes_per_sec.get() as f64;
+        BandwidthLimiter {
+            bytes_per_sec,
+            budget: Mutex::new(Budget { bytes: bytes_per_sec, refilled_at: Instant::now() }),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes_per_sec
+    }
+
+    // Wait until `bytes` more can be sent
+    pub async fn acquire(&self, bytes: usize) {
+        let mut budget = self.budget.lock().await;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(budget.refilled_at).as_secs_f64();
+        budget.bytes = (budget.bytes + elapsed * self.bytes_per_sec).min(self.bytes_per_sec);
+        budget.refilled_at = now;
+
+        if budget.bytes < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-budget.bytes / self.bytes_per_sec)).await;
+            budget.bytes = 0.0;
+            budget.refilled_at = Instant::now();
+        }
+        budget.bytes -= bytes as f64;
+    }
+}
+
+// Copy everything from the reader to the writer, at no more than the limiter's rate when there is one
+pub async fn copy_throttled<R, W>(reader: &mut R, writer: &mut W, bandwidth: Option<&BandwidthLimiter>) -> std::io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let Some(bandwidth) = bandwidth else {
+        return tokio::io::copy(reader, writer).await;
+    };
+
+    let mut buffer = vec![0; THROTTLED_CHUNK_SIZE];
+    let mut copied = 0;
+    loop {
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(copied);
+        }
+        bandwidth.acquire(n).await;
+        writer.write_all(&buffer[..n]).await?;
+        copied += n as u64;
+    }
+}
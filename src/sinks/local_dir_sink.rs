@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tonic::async_trait;
+use tracing::info;
+
+use crate::routing::object_location::ObjectLocation;
+use crate::sinks::bandwidth_limiter::{copy_throttled, BandwidthLimiter};
+use crate::sinks::storage_sink::{Delivery, SinkError, StorageSink};
+use crate::utils::file_digest::file_digest;
+
+// Copies the files under <root>/<object key>, or <root>/<bucket>/<object key> when routed to a bucket
+#[derive(Debug)]
+pub struct LocalDirSink {
+    root: PathBuf,
+}
+
+impl LocalDirSink {
+    pub fn new(root: PathBuf) -> Self {
+        LocalDirSink { root }
+    }
+}
+
+#[async_trait]
+impl StorageSink for LocalDirSink {
+    async fn store(
+        &self,
+        file_path: &str,
+        _tenant: &str,
+        location: &ObjectLocation,
+        bandwidth: Option<Arc<BandwidthLimiter>>,
+    ) -> Result<Delivery, SinkError> {
+        let target = match &location.bucket {
+            Some(bucket) => self.root.join(bucket).join(&location.key),
+            None => self.root.join(&location.key),
+        };
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Copy next to the target first so readers never see a partial file
+        let tmp_target = target.with_extension("tmp");
+        let mut source = tokio::fs::File::open(file_path).await?;
+        let mut copy = tokio::fs::File::create(&tmp_target).await?;
+        copy_throttled(&mut source, &mut copy, bandwidth.as_deref()).await?;
+        copy.sync_all().await?;
+        drop(copy);
+
+        // Read the copy back, so a bad disk or a file changed meanwhile fails the delivery
+        let (checksum, size) = file_digest(file_path).await?;
+        let (copied_checksum, copied_size) = file_digest(&tmp_target.to_string_lossy()).await?;
+        if copied_checksum != checksum || copied_size != size {
+            tokio::fs::remove_file(&tmp_target).await?;
+            return Err(format!(
+                "Copy of {} at {:?} has {} bytes with SHA-256 {}, expected {} bytes with SHA-256 {}",
+                file_path, tmp_target, copied_size, copied_checksum, size, checksum
+            ).into());
+        }
+        tokio::fs::rename(&tmp_target, &target).await?;
+
+        info!("Stored {} at {:?}", file_path, target);
+        Ok(Delivery::Verified)
+    }
+}
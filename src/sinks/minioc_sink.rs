@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use tonic::async_trait;
+
+use crate::client::minioc_client::MiniocClient;
+use crate::routing::object_location::ObjectLocation;
+use crate::sinks::bandwidth_limiter::BandwidthLimiter;
+use crate::sinks::storage_sink::{Delivery, SinkError, StorageSink};
+
+// Streams the files to the minioc gRPC service
+#[derive(Debug, Default)]
+pub struct MiniocSink {
+    client: Option<Arc<MiniocClient>>,
+}
+
+impl MiniocSink {
+    pub fn new(client: Option<Arc<MiniocClient>>) -> Self {
+        MiniocSink { client }
+    }
+}
+
+#[async_trait]
+impl StorageSink for MiniocSink {
+    async fn store(
+        &self,
+        file_path: &str,
+        tenant: &str,
+        location: &ObjectLocation,
+        bandwidth: Option<Arc<BandwidthLimiter>>,
+    ) -> Result<Delivery, SinkError> {
+        match &self.client {
+            Some(client) => client.send_log(file_path, tenant, location, bandwidth).await,
+            None => Err("minioc is not configured, set MINIOC_DOMAIN and MINIOC_PORT".into()),
+        }
+    }
+}
@@ -0,0 +1,7 @@
+pub mod storage_sink;
+pub mod bandwidth_limiter;
+pub mod local_dir_sink;
+pub mod minioc_sink;
+pub mod s3_sink;
+pub mod sink_settings;
+pub mod sink_registry;
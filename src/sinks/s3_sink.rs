@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use object_store::aws::{AmazonS3, AmazonS3Builder, Checksum};
+use object_store::buffered::BufWriter;
+use object_store::path::Path;
+use tokio::io::AsyncWriteExt;
+use tonic::async_trait;
+use tracing::{info, warn};
+
+use crate::routing::object_location::ObjectLocation;
+use crate::sinks::bandwidth_limiter::{copy_throttled, BandwidthLimiter};
+use crate::sinks::storage_sink::{Delivery, SinkError, StorageSink};
+
+// Puts the files in a bucket of an S3-compatible store, under their object key. Files routed to
+// another bucket go there instead, with the same endpoint and credentials.
+#[derive(Debug)]
+pub struct S3Sink {
+    bucket: String,
+    endpoint: Option<String>,
+    region: Option<String>,
+    // Store of each bucket used so far
+    stores: Mutex<HashMap<String, Arc<AmazonS3>>>,
+}
+
+impl S3Sink {
+    // Credentials come from the usual AWS_* environment variables
+    pub fn new(bucket: &str, endpoint: Option<&str>, region: Option<&str>) -> Result<Self, SinkError> {
+        let sink = S3Sink {
+            bucket: bucket.to_string(),
+            endpoint: endpoint.map(str::to_string),
+            region: region.map(str::to_string),
+            stores: Mutex::new(HashMap::new()),
+        };
+        // Fail on startup rather than on the first upload
+        sink.store_for(bucket)?;
+        Ok(sink)
+    }
+
+    fn store_for(&self, bucket: &str) -> Result<Arc<AmazonS3>, SinkError> {
+        let mut stores = self.stores.lock().unwrap();
+        if let Some(store) = stores.get(bucket) {
+            return Ok(store.clone());
+        }
+
+        // S3 checks every PUT and part against its SHA-256, so a stored file is a verified one
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .with_checksum_algorithm(Checksum::SHA256);
+        if let Some(endpoint) = &self.endpoint {
+            builder = builder.with_endpoint(endpoint).with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(region) = &self.region {
+            builder = builder.with_region(region);
+        }
+        let store = Arc::new(builder.build()?);
+        stores.insert(bucket.to_string(), store.clone());
+        Ok(store)
+    }
+}
+
+#[async_trait]
+impl StorageSink for S3Sink {
+    async fn store(
+        &self,
+        file_path: &str,
+        _tenant: &str,
+        location: &ObjectLocation,
+        bandwidth: Option<Arc<BandwidthLimiter>>,
+    ) -> Result<Delivery, SinkError> {
+        let bucket = location.bucket.as_deref().unwrap_or(&self.bucket);
+        let store = self.store_for(bucket)?;
+        let key = Path::from(location.key.as_str());
+
+        // Small files go up in one PUT, bigger ones as a multipart upload, without holding the
+        // whole file in memory
+        let mut file = tokio::fs::File::open(file_path).await?;
+        let mut writer = BufWriter::new(store, key.clone());
+        let written = match copy_throttled(&mut file, &mut writer, bandwidth.as_deref()).await {
+            Ok(_) => writer.shutdown().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            // Do not leave the parts of an unfinished multipart upload behind
+            if let Err(abort_error) = writer.abort().await {
+                warn!("Failed to abort the upload of {} to {}/{}: {}", file_path, bucket, key, abort_error);
+            }
+            return Err(e.into());
+        }
+
+        info!("Stored {} at {}/{}", file_path, bucket, key);
+        Ok(Delivery::Verified)
+    }
+}
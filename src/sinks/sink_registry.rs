@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use crate::client::minioc_client::MiniocClient;
+use crate::sinks::{minioc_sink::MiniocSink, storage_sink::StorageSink};
+use crate::sinks::sink_settings::ReplicationSettings;
+use crate::tenants::tenant_settings::{TenantSettings, DEFAULT_TENANT};
+
+// Destination of the tenants without replication settings, unless the "*" tenant has some
+pub const DEFAULT_DESTINATION: &str = "minioc";
+
+// A sink and the name its deliveries are tracked under
+#[derive(Debug, Clone)]
+pub struct Destination {
+    pub name: String,
+    pub sink: Arc<dyn StorageSink>,
+}
+
+// The destinations of a tenant and how many of them must receive a file
+#[derive(Debug, Clone)]
+pub struct TenantDestinations {
+    pub destinations: Vec<Destination>,
+    pub quorum: usize,
+}
+
+impl TenantDestinations {
+    fn minioc(minioc: Option<Arc<MiniocClient>>) -> Self {
+        TenantDestinations {
+            destinations: vec![Destination { name: DEFAULT_DESTINATION.to_string(), sink: Arc::new(MiniocSink::new(minioc)) }],
+            quorum: 1,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Destination> {
+        self.destinations.iter().find(|destination| destination.name == name)
+    }
+}
+
+// Destinations of each tenant; tenants without any upload to the "*" tenant's, or to minioc
+#[derive(Debug)]
+pub struct SinkRegistry {
+    default: TenantDestinations,
+    tenants: HashMap<String, TenantDestinations>,
+    uses_minioc: bool,
+}
+
+impl Default for SinkRegistry {
+    fn default() -> Self {
+        SinkRegistry::default_with(None)
+    }
+}
+
+impl SinkRegistry {
+    pub fn from_tenants(
+        tenants: &HashMap<String, TenantSettings>,
+        minioc: Option<Arc<MiniocClient>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        // Tenants without replication settings, listed or not, fall back to the "*" tenant's
+        // destinations, or to minioc
+        let mut registry = match tenants.get(DEFAULT_TENANT).and_then(|settings| settings.replication.as_ref()) {
+            Some(replication) => SinkRegistry {
+                default: build_destinations(DEFAULT_TENANT, replication, &minioc)?,
+                tenants: HashMap::new(),
+                uses_minioc: uses_minioc(replication),
+            },
+            None => SinkRegistry::default_with(minioc.clone()),
+        };
+        for (tenant, settings) in tenants {
+            let Some(replication) = &settings.replication else {
+                continue;
+            };
+            if tenant == DEFAULT_TENANT {
+                continue;
+            }
+            registry.uses_minioc |= uses_minioc(replication);
+            registry.tenants.insert(tenant.clone(), build_destinations(tenant, replication, &minioc)?);
+        }
+        Ok(registry)
+    }
+
+    fn default_with(minioc: Option<Arc<MiniocClient>>) -> Self {
+        SinkRegistry { default: TenantDestinations::minioc(minioc), tenants: HashMap::new(), uses_minioc: true }
+    }
+
+    pub fn for_tenant(&self, tenant: &str) -> &TenantDestinations {
+        self.tenants.get(tenant).unwrap_or(&self.default)
+    }
+
+    // Whether any tenant, listed or not, may deliver its files to minioc
+    pub fn uses_minioc(&self) -> bool {
+        self.uses_minioc
+    }
+}
+
+fn uses_minioc(replication: &ReplicationSettings) -> bool {
+    replication.destinations.iter().any(|destination| destination.sink.kind() == "minioc")
+}
+
+fn build_destinations(
+    tenant: &str,
+    replication: &ReplicationSettings,
+    minioc: &Option<Arc<MiniocClient>>,
+) -> Result<TenantDestinations, Box<dyn Error>> {
+    let mut destinations = Vec::new();
+    for destination in &replication.destinations {
+        let sink = destination.sink.build(minioc)
+            .map_err(|e| format!("Invalid sink {} for tenant {}: {}", destination.name, tenant, e))?;
+        destinations.push(Destination { name: destination.name.clone(), sink });
+    }
+    Ok(TenantDestinations { destinations, quorum: replication.quorum })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tenants(value: serde_json::Value) -> HashMap<String, TenantSettings> {
+        value
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(tenant, settings)| (tenant.clone(), TenantSettings::from_value(settings).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn unlisted_tenants_fall_back_to_minioc() {
+        let registry = SinkRegistry::from_tenants(&tenants(json!({
+            "acme": { "sink": { "type": "local", "path": "/tmp/out" } }
+        })), None).unwrap();
+        assert!(registry.uses_minioc());
+        assert_eq!(registry.for_tenant("other").destinations[0].name, DEFAULT_DESTINATION);
+    }
+
+    #[test]
+    fn default_tenant_replaces_minioc() {
+        let registry = SinkRegistry::from_tenants(&tenants(json!({
+            "*": { "sink": { "type": "local", "path": "/tmp/out" } },
+            "acme": {}
+        })), None).unwrap();
+        assert!(!registry.uses_minioc());
+        assert_eq!(registry.for_tenant("acme").destinations[0].name, "local");
+        assert_eq!(registry.for_tenant("other").destinations[0].name, "local");
+    }
+
+    #[test]
+    fn listed_minioc_sink_is_probed() {
+        let registry = SinkRegistry::from_tenants(&tenants(json!({
+            "*": { "sink": { "type": "local", "path": "/tmp/out" } },
+            "acme": { "sinks": [{ "type": "minioc" }, { "type": "local", "path": "/tmp/out" }] }
+        })), None).unwrap();
+        assert!(registry.uses_minioc());
+    }
+}
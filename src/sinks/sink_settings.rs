@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::client::minioc_client::MiniocClient;
+use crate::sinks::{local_dir_sink::LocalDirSink, minioc_sink::MiniocSink, s3_sink::S3Sink, storage_sink::StorageSink};
+
+// Where the files of a tenant are delivered
+#[derive(Debug, Clone)]
+pub enum SinkSettings {
+    Local { path: PathBuf },
+    Minioc,
+    S3 { bucket: String, endpoint: Option<String>, region: Option<String> },
+}
+
+impl SinkSettings {
+    pub fn from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        match value["type"].as_str() {
+            Some("local") => {
+                let path = value["path"].as_str().ok_or("A local sink needs a 'path'")?;
+                Ok(SinkSettings::Local { path: PathBuf::from(path) })
+            }
+            Some("minioc") => Ok(SinkSettings::Minioc),
+            Some("s3") => Ok(SinkSettings::S3 {
+                bucket: value["bucket"].as_str().ok_or("An s3 sink needs a 'bucket'")?.to_string(),
+                endpoint: value["endpoint"].as_str().map(str::to_string),
+                region: value["region"].as_str().map(str::to_string),
+            }),
+            Some(other) => Err(format!("Unknown sink type: {}", other).into()),
+            None => Err("A sink must have a 'type'".into()),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SinkSettings::Local { .. } => "local",
+            SinkSettings::Minioc => "minioc",
+            SinkSettings::S3 { .. } => "s3",
+        }
+    }
+
+    // Minioc sinks share the one minioc client
+    pub fn build(&self, minioc: &Option<Arc<MiniocClient>>) -> Result<Arc<dyn StorageSink>, Box<dyn Error>> {
+        Ok(match self {
+            SinkSettings::Local { path } => Arc::new(LocalDirSink::new(path.clone())),
+            SinkSettings::Minioc => Arc::new(MiniocSink::new(minioc.clone())),
+            SinkSettings::S3 { bucket, endpoint, region } => {
+                Arc::new(S3Sink::new(bucket, endpoint.as_deref(), region.as_deref()).map_err(|e| e.to_string())?)
+            }
+        })
+    }
+}
+
+// One of the destinations a tenant replicates its files to
+#[derive(Debug, Clone)]
+pub struct DestinationSettings {
+    // Tracks the delivery to this destination, defaults to the sink type
+    pub name: String,
+    pub sink: SinkSettings,
+}
+
+impl DestinationSettings {
+    pub fn from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let sink = SinkSettings::from_value(value)?;
+        let name = value["name"].as_str().unwrap_or(sink.kind()).to_string();
+        Ok(DestinationSettings { name, sink })
+    }
+}
+
+// Where the files of a tenant are replicated, and how many copies make a file delivered
+#[derive(Debug, Clone)]
+pub struct ReplicationSettings {
+    pub destinations: Vec<DestinationSettings>,
+    pub quorum: usize,
+}
+
+impl ReplicationSettings {
+    // Read either a single 'sink' or a list of 'sinks' with an optional 'sink_quorum' from the
+    // tenant settings. The quorum defaults to every destination.
+    pub fn from_tenant_value(value: &Value) -> Result<Option<Self>, Box<dyn Error>> {
+        let destinations = match (value.get("sink"), value.get("sinks")) {
+            (Some(_), Some(_)) => return Err("Set either 'sink' or 'sinks', not both".into()),
+            (Some(sink), None) => vec![DestinationSettings::from_value(sink)?],
+            (None, Some(Value::Array(sinks))) => {
+                sinks.iter().map(DestinationSettings::from_value).collect::<Result<Vec<_>, _>>()?
+            }
+            (None, Some(_)) => return Err("'sinks' must be an array".into()),
+            (None, None) => return Ok(None),
+        };
+        if destinations.is_empty() {
+            return Err("'sinks' must not be empty".into());
+        }
+        for (i, destination) in destinations.iter().enumerate() {
+            if destinations[..i].iter().any(|other| other.name == destination.name) {
+                return Err(format!("Sink name {} is used twice, give the sinks distinct 'name's", destination.name).into());
+            }
+        }
+
+        let quorum = match value.get("sink_quorum") {
+            Some(quorum) => quorum.as_u64().ok_or("'sink_quorum' must be a positive integer")? as usize,
+            None => destinations.len(),
+        };
+        if quorum == 0 || quorum > destinations.len() {
+            return Err(format!("'sink_quorum' must be between 1 and {}", destinations.len()).into());
+        }
+
+        Ok(Some(ReplicationSettings { destinations, quorum }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn error(value: Value) -> String {
+        ReplicationSettings::from_tenant_value(&value).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn without_sinks_there_is_no_replication() {
+        assert!(ReplicationSettings::from_tenant_value(&json!({})).unwrap().is_none());
+    }
+
+    #[test]
+    fn quorum_defaults_to_every_destination() {
+        let replication = ReplicationSettings::from_tenant_value(&json!({
+            "sinks": [{ "type": "minioc" }, { "name": "local", "type": "local", "path": "/tmp/parquetb" }]
+        }))
+        .unwrap()
+        .unwrap();
+        let names: Vec<_> = replication.destinations.iter().map(|destination| destination.name.as_str()).collect();
+        assert_eq!(names, ["minioc", "local"]);
+        assert_eq!(replication.quorum, 2);
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let message = error(json!({
+            "sinks": [{ "type": "minioc" }, { "type": "minioc" }]
+        }));
+        assert!(message.contains("Sink name minioc is used twice"), "{}", message);
+
+        let message = error(json!({
+            "sinks": [{ "name": "copy", "type": "minioc" }, { "name": "copy", "type": "local", "path": "/tmp/parquetb" }]
+        }));
+        assert!(message.contains("Sink name copy is used twice"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_a_zero_quorum() {
+        let message = error(json!({ "sinks": [{ "type": "minioc" }], "sink_quorum": 0 }));
+        assert!(message.contains("between 1 and 1"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_a_quorum_above_the_destinations() {
+        let message = error(json!({
+            "sinks": [{ "type": "minioc" }, { "name": "local", "type": "local", "path": "/tmp/parquetb" }],
+            "sink_quorum": 3
+        }));
+        assert!(message.contains("between 1 and 2"), "{}", message);
+    }
+}
@@ -0,0 +1,31 @@
+use std::error::Error;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use tonic::async_trait;
+
+use crate::routing::object_location::ObjectLocation;
+use crate::sinks::bandwidth_limiter::BandwidthLimiter;
+
+pub type SinkError = Box<dyn Error + Send + Sync>;
+
+// Whether the sink checked that the stored copy matches the local file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Verified,
+    Unverified,
+}
+
+// Destination the written Parquet files are delivered to
+#[async_trait]
+pub trait StorageSink: Debug + Send + Sync {
+    // Store the local file of a tenant at its resolved location, sending it no faster than the
+    // limiter shared by every upload allows
+    async fn store(
+        &self,
+        file_path: &str,
+        tenant: &str,
+        location: &ObjectLocation,
+        bandwidth: Option<Arc<BandwidthLimiter>>,
+    ) -> Result<Delivery, SinkError>;
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use arrow::datatypes::Schema;
+use tracing::info;
+
+use crate::metrics::registry::METRICS;
+use crate::utils::merge_schemas::merge_schemas;
+
+// Current schema of each tenant table. Schemas only grow: new metadata fields are
+// appended, and a field keeps the type it was first seen with.
+#[derive(Debug, Default)]
+pub struct SchemaRegistry {
+    schemas: Mutex<HashMap<(String, String), Schema>>,
+}
+
+impl SchemaRegistry {
+    // Merge the schema of a new batch into the table's schema and return the result,
+    // leaving the table's schema as it is until `commit`
+    pub fn evolve(&self, tenant: &str, table: &str, batch_schema: &Schema) -> Result<Schema, String> {
+        let schemas = self.schemas.lock().unwrap();
+        match schemas.get(&(tenant.to_string(), table.to_string())) {
+            Some(current) => merge_schemas(current, batch_schema)
+                .map_err(|e| format!("Table {} of tenant {}: {}", table, tenant, e)),
+            None => Ok(batch_schema.clone()),
+        }
+    }
+
+    // Record the schema of a file that was written. It is merged again, since
+    // another batch may have evolved the table in the meantime.
+    pub fn commit(&self, tenant: &str, table: &str, written_schema: &Schema) -> Result<(), String> {
+        let mut schemas = self.schemas.lock().unwrap();
+        let key = (tenant.to_string(), table.to_string());
+
+        let schema = match schemas.get(&key) {
+            Some(current) => {
+                let merged = merge_schemas(current, written_schema)
+                    .map_err(|e| format!("Table {} of tenant {}: {}", table, tenant, e))?;
+                if merged.fields().len() == current.fields().len() {
+                    return Ok(());
+                }
+                info!(
+                    "Schema of table {} for tenant {} gained {} fields",
+                    table,
+                    tenant,
+                    merged.fields().len() - current.fields().len()
+                );
+                merged
+            }
+            None => {
+                info!("Registered the schema of table {} for tenant {}", table, tenant);
+                written_schema.clone()
+            }
+        };
+
+        METRICS.schema_changes.with_label_values(&METRICS.table_labels(tenant, table)).inc();
+        schemas.insert(key, schema);
+        Ok(())
+    }
+}
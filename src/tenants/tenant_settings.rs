@@ -0,0 +1,61 @@
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use serde_json::Value;
+use tracing::info;
+
+use crate::dedup::deduplicator::DedupSettings;
+use crate::quotas::quota_manager::TenantLimits;
+use crate::sinks::sink_settings::ReplicationSettings;
+
+// Entry whose sinks are the default of every tenant without its own
+pub const DEFAULT_TENANT: &str = "*";
+
+// Per-tenant ingestion settings
+#[derive(Debug, Default)]
+pub struct TenantSettings {
+    pub dedup: Option<DedupSettings>,
+    pub limits: Option<TenantLimits>,
+    pub replication: Option<ReplicationSettings>,
+}
+
+impl TenantSettings {
+    pub fn from_value(value: &Value) -> Result<Self, Box<dyn Error>> {
+        let dedup = match value.get("dedup") {
+            Some(dedup) => Some(DedupSettings::from_value(dedup)?),
+            None => None,
+        };
+
+        let limits = match value.get("limits") {
+            Some(limits) => Some(TenantLimits::from_value(limits)?),
+            None => None,
+        };
+
+        let replication = ReplicationSettings::from_tenant_value(value)?;
+
+        Ok(TenantSettings { dedup, limits, replication })
+    }
+}
+
+// Load the tenant settings from a JSON file shaped as {"tenant": {...}}
+pub fn load_tenant_settings(path: &Path) -> Result<HashMap<String, TenantSettings>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let value: Value = serde_json::from_str(&content)?;
+
+    let tenants = value.as_object().ok_or("Tenant settings file must contain a JSON object keyed by tenant")?;
+
+    let mut settings = HashMap::new();
+    for (tenant, tenant_settings) in tenants {
+        let parsed = TenantSettings::from_value(tenant_settings)
+            .map_err(|e| format!("Invalid settings for tenant {}: {}", tenant, e))?;
+        if tenant == DEFAULT_TENANT && (parsed.dedup.is_some() || parsed.limits.is_some()) {
+            return Err(format!("Settings of tenant {} only take 'sink' or 'sinks'", DEFAULT_TENANT).into());
+        }
+        settings.insert(tenant.clone(), parsed);
+    }
+    info!("Loaded settings for {} tenants from {:?}", settings.len(), path);
+
+    Ok(settings)
+}
@@ -0,0 +1,32 @@
+
+use std::env;
+use std::error::Error;
+
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+// TLS settings of the connection to minioc, or None to connect in plaintext.
+// MINIOC_TLS_CA enables TLS with a custom CA, MINIOC_TLS_CERT and MINIOC_TLS_KEY add a client certificate.
+pub fn minioc_tls_config() -> Result<Option<ClientTlsConfig>, Box<dyn Error>> {
+    let Ok(ca_path) = env::var("MINIOC_TLS_CA") else {
+        return Ok(None);
+    };
+
+    let ca = std::fs::read(&ca_path)?;
+    let mut tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca));
+
+    if let Ok(domain) = env::var("MINIOC_TLS_DOMAIN") {
+        tls = tls.domain_name(domain);
+    }
+
+    match (env::var("MINIOC_TLS_CERT"), env::var("MINIOC_TLS_KEY")) {
+        (Ok(cert_path), Ok(key_path)) => {
+            let cert = std::fs::read(cert_path)?;
+            let key = std::fs::read(key_path)?;
+            tls = tls.identity(Identity::from_pem(cert, key));
+        }
+        (Err(_), Err(_)) => {}
+        _ => return Err("MINIOC_TLS_CERT and MINIOC_TLS_KEY must be set together".into()),
+    }
+
+    Ok(Some(tls))
+}
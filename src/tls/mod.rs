@@ -0,0 +1,2 @@
+pub mod server_tls_config;
+pub mod minioc_tls_config;
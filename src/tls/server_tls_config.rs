@@ -0,0 +1,33 @@
+
+use std::env;
+use std::error::Error;
+
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tracing::info;
+
+// TLS settings of the gRPC server, or None to serve plaintext.
+// Setting PARQUETB_TLS_CLIENT_CA also requires clients to present a certificate signed by that CA.
+pub fn server_tls_config() -> Result<Option<ServerTlsConfig>, Box<dyn Error>> {
+    let (cert_path, key_path) = match (env::var("PARQUETB_TLS_CERT"), env::var("PARQUETB_TLS_KEY")) {
+        (Ok(cert_path), Ok(key_path)) => (cert_path, key_path),
+        // Asking for client certificates without serving TLS must not fall back to plaintext
+        (Err(_), Err(_)) if env::var("PARQUETB_TLS_CLIENT_CA").is_ok() => {
+            return Err("PARQUETB_TLS_CLIENT_CA needs PARQUETB_TLS_CERT and PARQUETB_TLS_KEY".into());
+        }
+        (Err(_), Err(_)) => return Ok(None),
+        _ => return Err("PARQUETB_TLS_CERT and PARQUETB_TLS_KEY must be set together".into()),
+    };
+
+    let cert = std::fs::read(&cert_path)?;
+    let key = std::fs::read(&key_path)?;
+    let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+    info!("Serving TLS with certificate {}", cert_path);
+
+    if let Ok(client_ca_path) = env::var("PARQUETB_TLS_CLIENT_CA") {
+        let client_ca = std::fs::read(&client_ca_path)?;
+        tls = tls.client_ca_root(Certificate::from_pem(client_ca));
+        info!("Requiring client certificates signed by {}", client_ca_path);
+    }
+
+    Ok(Some(tls))
+}
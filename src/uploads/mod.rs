@@ -0,0 +1,2 @@
+pub mod upload_queue;
+pub mod spooled_file;
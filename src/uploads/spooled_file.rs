@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+use parquet::file::metadata::KeyValue;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+use crate::routing::object_location::ObjectLocation;
+
+// Key-value metadata recording where a spooled file is to be delivered
+const TENANT_KEY: &str = "parquetb.tenant";
+const OBJECT_KEY_KEY: &str = "parquetb.object_key";
+const BUCKET_KEY: &str = "parquetb.bucket";
+// Relative to the tenant, the only one written before routing
+const OBJECT_NAME_KEY: &str = "parquetb.object_name";
+
+pub fn spool_metadata(tenant: &str, location: &ObjectLocation) -> Vec<KeyValue> {
+    let mut metadata = vec![
+        KeyValue::new(TENANT_KEY.to_string(), tenant.to_string()),
+        KeyValue::new(OBJECT_KEY_KEY.to_string(), location.key.clone()),
+        KeyValue::new(OBJECT_NAME_KEY.to_string(), location.object_name.clone()),
+    ];
+    if let Some(bucket) = &location.bucket {
+        metadata.push(KeyValue::new(BUCKET_KEY.to_string(), bucket.clone()));
+    }
+    metadata
+}
+
+// Destination of a spooled file, read back from its footer
+pub struct SpooledFile {
+    pub tenant: Option<String>,
+    pub location: Option<ObjectLocation>,
+}
+
+// Parse the footer of a spooled file; fails when the file is truncated or not Parquet
+pub fn read_spooled_file(path: &Path) -> Result<SpooledFile, Box<dyn Error>> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let metadata = reader.metadata().file_metadata().key_value_metadata();
+    let value = |key: &str| {
+        metadata
+            .and_then(|entries| entries.iter().find(|entry| entry.key == key))
+            .and_then(|entry| entry.value.clone())
+    };
+
+    let tenant = value(TENANT_KEY);
+    let location = match (value(OBJECT_KEY_KEY), value(OBJECT_NAME_KEY), &tenant) {
+        (Some(key), Some(object_name), _) => Some(ObjectLocation { bucket: value(BUCKET_KEY), key, object_name }),
+        (Some(key), None, Some(tenant)) => Some(ObjectLocation::without_object_name(tenant, value(BUCKET_KEY), &key)),
+        (None, Some(object_name), Some(tenant)) => Some(ObjectLocation::unrouted(tenant, &object_name)),
+        _ => None,
+    };
+
+    Ok(SpooledFile { tenant, location })
+}
@@ -0,0 +1,558 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+use crate::metrics::registry::{GaugeGuard, METRICS};
+use crate::retention::retention_policy::RetentionPolicy;
+use crate::routing::object_location::ObjectLocation;
+use crate::sinks::bandwidth_limiter::BandwidthLimiter;
+use crate::sinks::sink_registry::{SinkRegistry, TenantDestinations, DEFAULT_DESTINATION};
+use crate::sinks::storage_sink::Delivery;
+use crate::uploads::spooled_file::read_spooled_file;
+
+const JOURNAL_FILE: &str = "journal.json";
+// Spooled files that are not valid Parquet are moved here
+const QUARANTINE_DIR: &str = "quarantine";
+
+// Where files wait for delivery and how delivery is retried
+#[derive(Debug, Clone)]
+pub struct UploadSettings {
+    // Written files wait here until they are delivered
+    pub spool_dir: PathBuf,
+    // Delivered files are moved here, unless they are deleted right away
+    pub output_dir: PathBuf,
+    pub retention: RetentionPolicy,
+    // A file is undeliverable after this many failed attempts
+    pub max_attempts: u32,
+    // Files delivered at the same time
+    pub max_concurrent_uploads: usize,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    // Combined throughput of all uploads, unlimited when None
+    pub max_bytes_per_sec: Option<NonZeroU64>,
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        UploadSettings {
+            spool_dir: PathBuf::from("spool"),
+            output_dir: PathBuf::from("."),
+            retention: RetentionPolicy::default(),
+            max_attempts: 10,
+            max_concurrent_uploads: 4,
+            base_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            max_bytes_per_sec: None,
+        }
+    }
+}
+
+// Delivery state of a file at one destination
+#[derive(Debug, Clone)]
+pub struct DestinationState {
+    pub name: String,
+    pub attempts: u32,
+    // Unix time in milliseconds of the next attempt
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    pub delivered: bool,
+    // The sink checked its copy against the spooled file
+    pub verified: bool,
+    // Out of attempts
+    pub failed: bool,
+}
+
+impl DestinationState {
+    fn new(name: &str) -> Self {
+        DestinationState {
+            name: name.to_string(),
+            attempts: 0,
+            next_attempt_at: 0,
+            last_error: None,
+            delivered: false,
+            verified: false,
+            failed: false,
+        }
+    }
+
+    fn pending(&self) -> bool {
+        !self.delivered && !self.failed
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "name": self.name,
+            "attempts": self.attempts,
+            "next_attempt_at": self.next_attempt_at,
+            "last_error": self.last_error,
+            "delivered": self.delivered,
+            "verified": self.verified,
+            "failed": self.failed,
+        })
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(DestinationState {
+            name: value["name"].as_str()?.to_string(),
+            attempts: value["attempts"].as_u64().unwrap_or_default() as u32,
+            next_attempt_at: value["next_attempt_at"].as_u64().unwrap_or_default(),
+            last_error: value["last_error"].as_str().map(str::to_string),
+            delivered: value["delivered"].as_bool().unwrap_or_default(),
+            verified: value["verified"].as_bool().unwrap_or_default(),
+            failed: value["failed"].as_bool().unwrap_or_default(),
+        })
+    }
+}
+
+// A spooled file and its delivery state at each destination of its tenant
+#[derive(Debug, Clone)]
+pub struct QueuedUpload {
+    pub file_name: String,
+    pub tenant: String,
+    pub location: ObjectLocation,
+    pub destinations: Vec<DestinationState>,
+    // A quorum of destinations received the file, the others are still retried
+    pub delivered: bool,
+    // The quorum can no longer be reached, waiting for an operator
+    pub undeliverable: bool,
+}
+
+impl QueuedUpload {
+    fn new(file_name: &str, tenant: &str, location: &ObjectLocation, destinations: &TenantDestinations) -> Self {
+        let mut upload = QueuedUpload {
+            file_name: file_name.to_string(),
+            tenant: tenant.to_string(),
+            location: location.clone(),
+            destinations: Vec::new(),
+            delivered: false,
+            undeliverable: false,
+        };
+        upload.sync_destinations(destinations);
+        upload
+    }
+
+    // Track exactly the configured destinations of the tenant, which may have changed since the
+    // journal was written
+    fn sync_destinations(&mut self, destinations: &TenantDestinations) {
+        self.destinations.retain(|state| {
+            let configured = destinations.get(&state.name).is_some();
+            if !configured {
+                warn!("Destination {} of {} is no longer configured, forgetting it", state.name, self.file_name);
+            }
+            configured
+        });
+        for destination in &destinations.destinations {
+            if !self.destinations.iter().any(|state| state.name == destination.name) {
+                self.destinations.push(DestinationState::new(&destination.name));
+            }
+        }
+    }
+
+    fn deliveries(&self) -> usize {
+        self.destinations.iter().filter(|state| state.delivered).count()
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "file_name": self.file_name,
+            "tenant": self.tenant,
+            "object_key": self.location.key,
+            "object_name": self.location.object_name,
+            "bucket": self.location.bucket,
+            "destinations": self.destinations.iter().map(DestinationState::to_value).collect::<Vec<_>>(),
+            "delivered": self.delivered,
+            "undeliverable": self.undeliverable,
+        })
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let tenant = value["tenant"].as_str()?;
+        // Entries written before routing only hold the object name relative to the tenant
+        let bucket = value["bucket"].as_str().map(str::to_string);
+        let location = match (value["object_key"].as_str(), value["object_name"].as_str()) {
+            (Some(key), Some(object_name)) => ObjectLocation { bucket, key: key.to_string(), object_name: object_name.to_string() },
+            (Some(key), None) => ObjectLocation::without_object_name(tenant, bucket, key),
+            (None, object_name) => ObjectLocation::unrouted(tenant, object_name?),
+        };
+        let undeliverable = value["undeliverable"].as_bool().unwrap_or_default();
+        // Entries written before replication tracked their one delivery to the default destination
+        let destinations = match value["destinations"].as_array() {
+            Some(states) => states.iter().filter_map(DestinationState::from_value).collect(),
+            None => vec![DestinationState {
+                attempts: value["attempts"].as_u64().unwrap_or_default() as u32,
+                next_attempt_at: value["next_attempt_at"].as_u64().unwrap_or_default(),
+                last_error: value["last_error"].as_str().map(str::to_string),
+                failed: undeliverable,
+                ..DestinationState::new(DEFAULT_DESTINATION)
+            }],
+        };
+        Some(QueuedUpload {
+            file_name: value["file_name"].as_str()?.to_string(),
+            tenant: tenant.to_string(),
+            location,
+            destinations,
+            delivered: value["delivered"].as_bool().unwrap_or_default(),
+            undeliverable,
+        })
+    }
+}
+
+// Persistent queue of the files to deliver. A file is durable once it is enqueued: the journal
+// in the spool directory records it, and delivery to each destination of its tenant is retried
+// with exponential backoff and jitter until it succeeds or runs out of attempts. The file leaves
+// the spool once every destination has settled and a quorum of them has received it.
+#[derive(Debug, Default)]
+pub struct UploadQueue {
+    settings: UploadSettings,
+    journal_path: Option<PathBuf>,
+    sinks: SinkRegistry,
+    bandwidth: Option<Arc<BandwidthLimiter>>,
+    uploads: Mutex<HashMap<String, QueuedUpload>>,
+    // File and destination of each running delivery
+    in_flight: Mutex<HashSet<(String, String)>>,
+    // Set on shutdown: no delivery starts anymore, the queued ones wait for the next start
+    stopped: AtomicBool,
+    wake: Notify,
+    idle: Notify,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+impl UploadQueue {
+    // Open the spool directory and read its journal
+    pub fn open(settings: UploadSettings, sinks: SinkRegistry) -> Result<Self, Box<dyn Error>> {
+        std::fs::create_dir_all(&settings.spool_dir)?;
+        std::fs::create_dir_all(&settings.output_dir)?;
+        // Delivered files must be told apart from the ones still waiting
+        if settings.spool_dir.canonicalize()? == settings.output_dir.canonicalize()? {
+            return Err("The spool and output directories must differ".into());
+        }
+
+        let journal_path = settings.spool_dir.join(JOURNAL_FILE);
+        let mut uploads = HashMap::new();
+        if journal_path.exists() {
+            let content = std::fs::read_to_string(&journal_path)?;
+            let Value::Array(entries) = serde_json::from_str::<Value>(&content)? else {
+                return Err(format!("{:?} must contain a JSON array", journal_path).into());
+            };
+            for mut upload in entries.iter().filter_map(QueuedUpload::from_value) {
+                upload.sync_destinations(sinks.for_tenant(&upload.tenant));
+                uploads.insert(upload.file_name.clone(), upload);
+            }
+        }
+        info!("Loaded {} queued uploads from {:?}", uploads.len(), journal_path);
+
+        let queue = UploadQueue {
+            bandwidth: settings.max_bytes_per_sec.map(|limit| Arc::new(BandwidthLimiter::new(limit))),
+            settings,
+            journal_path: Some(journal_path),
+            sinks,
+            uploads: Mutex::new(uploads),
+            ..Default::default()
+        };
+        // Files whose remaining destinations were removed may be settled already
+        let mut uploads = queue.uploads.lock().unwrap();
+        let file_names: Vec<_> = uploads.keys().cloned().collect();
+        for file_name in file_names {
+            queue.settle(&mut uploads, &file_name);
+        }
+        queue.update_gauges(&uploads);
+        drop(uploads);
+        Ok(queue)
+    }
+
+    pub fn spool_dir(&self) -> &Path {
+        &self.settings.spool_dir
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.settings.output_dir
+    }
+
+    // Reconcile the spool with the journal after a restart: quarantine the files whose Parquet
+    // footer does not parse, forget the entries whose file is gone, and queue the valid files
+    // the journal does not know about
+    pub fn recover(&self) -> Result<(), Box<dyn Error>> {
+        let spool_dir = &self.settings.spool_dir;
+        let mut uploads = self.uploads.lock().unwrap();
+
+        uploads.retain(|file_name, _| {
+            let exists = spool_dir.join(file_name).exists();
+            if !exists {
+                warn!("Queued file {} is missing from the spool, forgetting it", file_name);
+            }
+            exists
+        });
+
+        let mut recovered = 0;
+        for entry in std::fs::read_dir(spool_dir)? {
+            let path = entry?.path();
+            if !path.is_file() || path.extension().is_none_or(|extension| extension != "parquet") {
+                continue;
+            }
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+            // Only a file whose footer cannot be read is corrupt. Files written before the
+            // metadata existed keep their journal entry, or stay where they are.
+            match read_spooled_file(&path) {
+                Ok(_) if uploads.contains_key(&file_name) => {}
+                Ok(spooled) => match spooled.tenant.zip(spooled.location) {
+                    Some((tenant, location)) => {
+                        info!("Queuing spooled file {} for tenant {}", file_name, tenant);
+                        let destinations = self.sinks.for_tenant(&tenant);
+                        uploads.insert(file_name.clone(), QueuedUpload::new(&file_name, &tenant, &location, destinations));
+                        recovered += 1;
+                    }
+                    None => warn!("Spooled file {} has no destination in its metadata, leaving it in place", file_name),
+                },
+                Err(e) => {
+                    warn!("Quarantining spooled file {}: {}", file_name, e);
+                    uploads.remove(&file_name);
+                    let quarantine_dir = spool_dir.join(QUARANTINE_DIR);
+                    std::fs::create_dir_all(&quarantine_dir)?;
+                    std::fs::rename(&path, quarantine_dir.join(&file_name))?;
+                }
+            }
+        }
+        info!("Recovered {} spooled files, {} uploads queued", recovered, uploads.len());
+
+        self.persist(&uploads)?;
+        self.update_gauges(&uploads);
+        Ok(())
+    }
+
+    // Make a spooled file durable and queue it for delivery
+    pub fn enqueue(&self, file_name: &str, tenant: &str, location: &ObjectLocation) -> Result<(), Box<dyn Error>> {
+        File::open(self.settings.spool_dir.join(file_name))?.sync_all()?;
+
+        let mut uploads = self.uploads.lock().unwrap();
+        let destinations = self.sinks.for_tenant(tenant);
+        uploads.insert(file_name.to_string(), QueuedUpload::new(file_name, tenant, location, destinations));
+        if let Err(e) = self.persist(&uploads) {
+            uploads.remove(file_name);
+            return Err(e);
+        }
+        self.update_gauges(&uploads);
+        drop(uploads);
+
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    // Files that can no longer reach their quorum, of one tenant or of all of them
+    pub fn undeliverable(&self, tenant: Option<&str>) -> Vec<QueuedUpload> {
+        let mut undeliverable: Vec<_> = self.uploads.lock().unwrap()
+            .values()
+            .filter(|upload| upload.undeliverable && tenant.is_none_or(|tenant| upload.tenant == tenant))
+            .cloned()
+            .collect();
+        undeliverable.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        undeliverable
+    }
+
+    // Deliver the due files in the background, until the queue is stopped
+    pub fn spawn(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                if self.stopped.load(Ordering::SeqCst) {
+                    info!("Upload queue stopped, remaining files stay in the spool");
+                    return;
+                }
+
+                let now = now_millis();
+                let mut next_due = None;
+                let mut due = Vec::new();
+                {
+                    let uploads = self.uploads.lock().unwrap();
+                    let mut in_flight = self.in_flight.lock().unwrap();
+                    let mut pending: Vec<_> = uploads.values()
+                        .filter(|upload| !upload.undeliverable)
+                        .flat_map(|upload| upload.destinations.iter().map(move |state| (upload, state)))
+                        .filter(|(upload, state)| {
+                            state.pending() && !in_flight.contains(&(upload.file_name.clone(), state.name.clone()))
+                        })
+                        .collect();
+                    // Oldest deliveries first, as many as there are free upload slots
+                    pending.sort_by_key(|(upload, state)| (state.next_attempt_at, upload.file_name.as_str(), state.name.as_str()));
+                    for (upload, state) in pending {
+                        if state.next_attempt_at > now {
+                            next_due = Some(next_due.map_or(state.next_attempt_at, |at: u64| at.min(state.next_attempt_at)));
+                        } else if in_flight.len() < self.settings.max_concurrent_uploads.max(1) {
+                            in_flight.insert((upload.file_name.clone(), state.name.clone()));
+                            due.push((upload.clone(), state.name.clone()));
+                        }
+                        // Otherwise the next delivery to finish wakes the loop up
+                    }
+                }
+
+                for (upload, destination) in due {
+                    let queue = self.clone();
+                    tokio::spawn(async move { queue.deliver(upload, destination).await });
+                }
+
+                let sleep = next_due.map_or(Duration::from_secs(60), |at| Duration::from_millis(at.saturating_sub(now)));
+                tokio::select! {
+                    _ = self.wake.notified() => {}
+                    _ = tokio::time::sleep(sleep) => {}
+                }
+            }
+        });
+    }
+
+    async fn deliver(&self, upload: QueuedUpload, destination: String) {
+        let _in_flight = GaugeGuard::new(&METRICS.uploads_in_flight);
+        let spooled = self.settings.spool_dir.join(&upload.file_name);
+        let destinations = self.sinks.for_tenant(&upload.tenant);
+
+        let timer = METRICS.upload_duration.start_timer();
+        let result = match destinations.get(&destination) {
+            Some(target) => {
+                target.sink.store(&spooled.to_string_lossy(), &upload.tenant, &upload.location, self.bandwidth.clone()).await
+            }
+            None => Err(format!("destination {} is not configured", destination).into()),
+        };
+        timer.observe_duration();
+
+        let mut uploads = self.uploads.lock().unwrap();
+        if let Some(queued) = uploads.get_mut(&upload.file_name) {
+            if let Some(state) = queued.destinations.iter_mut().find(|state| state.name == destination) {
+                match result {
+                    Ok(delivery) => {
+                        info!("Delivered {} to {} for tenant {} ({:?})", upload.file_name, destination, upload.tenant, delivery);
+                        state.delivered = true;
+                        state.verified = delivery == Delivery::Verified;
+                        state.last_error = None;
+                    }
+                    Err(e) => {
+                        METRICS.upload_failures.with_label_values(&[METRICS.tenant_label(&upload.tenant), &destination]).inc();
+                        state.attempts += 1;
+                        state.last_error = Some(e.to_string());
+                        if state.attempts >= self.settings.max_attempts {
+                            error!("Giving up on {} at {} after {} attempts: {}", upload.file_name, destination, state.attempts, e);
+                            state.failed = true;
+                        } else {
+                            let backoff = self.backoff(state.attempts);
+                            warn!("Delivery of {} to {} failed, retrying in {:?}: {}", upload.file_name, destination, backoff, e);
+                            state.next_attempt_at = now_millis() + backoff.as_millis() as u64;
+                        }
+                    }
+                }
+            }
+        }
+        self.settle(&mut uploads, &upload.file_name);
+        if let Err(e) = self.persist(&uploads) {
+            error!("Failed to persist the upload journal: {}", e);
+        }
+        self.update_gauges(&uploads);
+        drop(uploads);
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+        in_flight.remove(&(upload.file_name.clone(), destination));
+        if in_flight.is_empty() {
+            self.idle.notify_waiters();
+        }
+        drop(in_flight);
+        self.wake.notify_one();
+    }
+
+    // Mark a file delivered once a quorum received it, clear it from the spool once every
+    // destination has settled, or flag it undeliverable as soon as the quorum is out of reach
+    fn settle(&self, uploads: &mut HashMap<String, QueuedUpload>, file_name: &str) {
+        let Some(upload) = uploads.get_mut(file_name) else {
+            return;
+        };
+        let quorum = self.sinks.for_tenant(&upload.tenant).quorum;
+        let delivered = upload.deliveries();
+        let pending = upload.destinations.iter().filter(|state| state.pending()).count();
+
+        if !upload.delivered && delivered >= quorum {
+            info!("{} is delivered, {} of {} destinations received it", file_name, delivered, upload.destinations.len());
+            upload.delivered = true;
+        }
+
+        if delivered + pending < quorum {
+            if !upload.undeliverable {
+                error!("{} can no longer reach its quorum of {} destinations", file_name, quorum);
+                upload.undeliverable = true;
+            }
+        } else if pending == 0 {
+            // Only copies every destination verified may replace the local file
+            let verified = upload.destinations.iter().filter(|state| state.delivered).all(|state| state.verified);
+            uploads.remove(file_name);
+            let spooled = self.settings.spool_dir.join(file_name);
+            let delete = self.settings.retention == RetentionPolicy::DeleteAfterUpload;
+            if delete && !verified {
+                warn!("{} was delivered without verification, keeping it in the output directory", file_name);
+            }
+            let cleared = if delete && verified {
+                std::fs::remove_file(&spooled)
+            } else {
+                std::fs::rename(&spooled, self.settings.output_dir.join(file_name))
+            };
+            if let Err(e) = cleared {
+                error!("Failed to clear delivered file {:?} from the spool: {}", spooled, e);
+            }
+        }
+    }
+
+    // Exponential backoff with jitter: between half and all of base * 2^(attempts - 1), capped
+    fn backoff(&self, attempts: u32) -> Duration {
+        let exponential = self.settings.base_backoff.saturating_mul(1 << attempts.saturating_sub(1).min(20));
+        let capped = exponential.min(self.settings.max_backoff).as_millis() as u64;
+        Duration::from_millis(capped / 2 + rand::thread_rng().gen_range(0..=capped / 2))
+    }
+
+    // Start no more deliveries; the running ones finish
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    // Wait until no delivery is running, or the timeout expires. Returns whether they all finished.
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        let wait = async {
+            loop {
+                let idle = self.idle.notified();
+                if self.in_flight.lock().unwrap().is_empty() {
+                    return;
+                }
+                idle.await;
+            }
+        };
+        tokio::time::timeout(timeout, wait).await.is_ok()
+    }
+
+    fn update_gauges(&self, uploads: &HashMap<String, QueuedUpload>) {
+        let undeliverable = uploads.values().filter(|upload| upload.undeliverable).count() as i64;
+        let replicating = uploads.values().filter(|upload| upload.delivered).count() as i64;
+        METRICS.upload_queue_depth.set(uploads.len() as i64 - undeliverable - replicating);
+        METRICS.undeliverable_files.set(undeliverable);
+        METRICS.replicating_files.set(replicating);
+    }
+
+    fn persist(&self, uploads: &HashMap<String, QueuedUpload>) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.journal_path else {
+            return Ok(());
+        };
+        let entries: Vec<Value> = uploads.values().map(QueuedUpload::to_value).collect();
+
+        // Write to a temporary file first so a crash never leaves a truncated journal
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&entries)?)?;
+        File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+
+use serde_json::{json, Value};
+
+use crate::parquetb_service::parquetb::LogEntry;
+
+// Convert LogEntry to serde_json::Value for processing
+pub fn log_entry_to_value(entry: LogEntry) -> Value {
+    json!({
+        "datetime": entry.datetime,
+        "tenant_name": entry.tenant_name,
+        "table": entry.table,
+        "item_id": entry.item_id,
+        "status": entry.status,
+        "qty": entry.qty,
+        "metadata": entry.metadata,
+        "producer_id": entry.producer_id,
+        "sequence": entry.sequence,
+    })
+}
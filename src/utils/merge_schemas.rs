@@ -0,0 +1,24 @@
+
+use arrow::datatypes::Schema;
+
+// Extend `base` with the fields of `other` it lacks, failing when a shared field changes type
+pub fn merge_schemas(base: &Schema, other: &Schema) -> Result<Schema, String> {
+    let mut fields: Vec<_> = base.fields().iter().cloned().collect();
+
+    for field in other.fields() {
+        match base.field_with_name(field.name()) {
+            Ok(existing) if existing.data_type() != field.data_type() => {
+                return Err(format!(
+                    "Field {} changed type from {} to {}",
+                    field.name(),
+                    existing.data_type(),
+                    field.data_type()
+                ));
+            }
+            Ok(_) => {}
+            Err(_) => fields.push(field.clone()),
+        }
+    }
+
+    Ok(Schema::new(fields))
+}
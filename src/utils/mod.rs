@@ -3,4 +3,9 @@ pub mod infer_metadata_schema;
 pub mod build_schema;
 pub mod log_entry_to_arrays;
 pub mod write_parquet_file;
-
+pub mod log_entry_to_value;
+pub mod reserve_file_name;
+pub mod merge_schemas;
+pub mod parse_interval;
+pub mod parse_count;
+pub mod file_digest;
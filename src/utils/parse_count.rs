@@ -0,0 +1,10 @@
+use std::error::Error;
+
+// Parse a size or count setting that must be at least 1
+pub fn parse_count(name: &str, value: &str) -> Result<usize, Box<dyn Error>> {
+    match value.parse::<usize>() {
+        Ok(0) => Err(format!("{} must be at least 1", name).into()),
+        Ok(count) => Ok(count),
+        Err(e) => Err(format!("Invalid {}: {}", name, e).into()),
+    }
+}
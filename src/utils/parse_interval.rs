@@ -0,0 +1,11 @@
+use std::error::Error;
+use std::time::Duration;
+
+// Parse a whole number of seconds for a periodic task; tokio intervals panic on zero
+pub fn parse_interval(name: &str, value: &str) -> Result<Duration, Box<dyn Error>> {
+    match value.parse::<u64>() {
+        Ok(0) => Err(format!("{} must be at least 1 second", name).into()),
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(e) => Err(format!("Invalid {}: {}", name, e).into()),
+    }
+}
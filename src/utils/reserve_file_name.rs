@@ -0,0 +1,58 @@
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+// Atomically claim a file name derived from `base` in `dir`, adding a counter when it is already
+// taken there or in one of the `also_in` directories files move to later
+pub fn reserve_file_name(dir: &Path, also_in: &[&Path], base: &str, extension: &str) -> std::io::Result<String> {
+    let mut attempt = 0;
+    loop {
+        let file_name = if attempt == 0 {
+            format!("{}.{}", base, extension)
+        } else {
+            format!("{}_{}.{}", base, attempt, extension)
+        };
+
+        match OpenOptions::new().write(true).create_new(true).open(dir.join(&file_name)) {
+            Ok(_) if also_in.iter().any(|other| other.join(&file_name).exists()) => {
+                std::fs::remove_file(dir.join(&file_name))?;
+                attempt += 1;
+            }
+            Ok(_) => return Ok(file_name),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// A reserved file that is removed again when dropped, unless it is kept
+pub struct ReservedFile {
+    path: PathBuf,
+    kept: bool,
+}
+
+impl ReservedFile {
+    pub fn new(path: PathBuf) -> Self {
+        ReservedFile { path, kept: false }
+    }
+
+    // The file is complete and handed over, leave it in place
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for ReservedFile {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove the abandoned file {:?}: {}", self.path, e),
+        }
+    }
+}
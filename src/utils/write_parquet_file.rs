@@ -10,7 +10,7 @@ use parquet::arrow::ArrowWriter;
 use parquet::file::properties::WriterProperties;
 
 // Write Arrow arrays to a Parquet file
-pub fn write_parquet_file(file_path: &str, schema: Arc<Schema>, arrays: Vec<ArrayRef>) -> Result<(), Box<dyn Error>> {
+pub fn write_parquet_file(file_path: &str, schema: Arc<Schema>, arrays: Vec<ArrayRef>, props: WriterProperties) -> Result<(), Box<dyn Error>> {
     // Check if the number of arrays matches the number of fields in the schema
     if arrays.len() != schema.fields().len() {
         return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidInput,
@@ -18,7 +18,6 @@ pub fn write_parquet_file(file_path: &str, schema: Arc<Schema>, arrays: Vec<Arra
     }
 
     let file = File::create(file_path)?;
-    let props = WriterProperties::builder().build();
 
     // Create a new RecordBatch
     let batch = RecordBatch::try_new(schema.clone(), arrays)?;